@@ -0,0 +1,153 @@
+// Ingests a large synthetic log and reports how fast lines are applied at the
+// start of the history compared to the end. With in-place updates the two
+// rates should be about the same; when every update cloned all of the games
+// seen so far, the late rate fell off in proportion to the history size.
+//
+// Run with `cargo bench --bench ingest`, optionally passing the number of
+// matches to generate after `--`.
+
+extern crate squadlog;
+
+use std::env;
+use std::time::Instant;
+
+use squadlog::{apply_line, GameState, Parser};
+
+const PLAYERS: [&str; 8] = [
+    "Alpha", "Bravo", "Charlie", "Delta", "Echo", "Foxtrot", "Golf", "Hotel",
+];
+const LINES_PER_MATCH: usize = 200;
+
+// A tiny linear congruential generator, so the log is the same every run.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, n: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        ((self.0 >> 33) as usize) % n
+    }
+}
+
+fn timestamp(t: u64) -> String {
+    let ms = t % 1000;
+    let s = (t / 1000) % 60;
+    let m = (t / 60_000) % 60;
+    let h = (t / 3_600_000) % 24;
+    let d = 1 + (t / 86_400_000) % 28;
+    let mo = 1 + (t / (86_400_000 * 28)) % 12;
+    let y = 2020 + t / (86_400_000 * 28 * 12);
+    format!(
+        "{:04}.{:02}.{:02}-{:02}.{:02}.{:02}:{:03}",
+        y, mo, d, h, m, s, ms
+    )
+}
+
+fn synthetic_match(rng: &mut Lcg, t: &mut u64, out: &mut Vec<String>) {
+    let mut line = |t: &mut u64, category: &str, msg: String| {
+        *t += 250;
+        out.push(format!("[{}][  0]{}: {}", timestamp(*t), category, msg));
+    };
+
+    line(
+        t,
+        "LogWorld",
+        String::from("StartLoadingDestination to: /Game/Maps/Narva/Gameplay_Layers/Narva_AAS_v1"),
+    );
+    for p in &PLAYERS {
+        line(
+            t,
+            "LogSquadTrace",
+            format!(
+                "[DedicatedServer]ASQPlayerController::ChangeState(): PC={} OldState=Inactive NewState=Playing",
+                p
+            ),
+        );
+        line(
+            t,
+            "LogSquadTrace",
+            format!(
+                "[DedicatedServer]ASQPlayerController::SetCurrentRole(): On Server PC={} NewRole=USA_Rifleman_01",
+                p
+            ),
+        );
+    }
+    for _i in 0..LINES_PER_MATCH - 2 - 2 * PLAYERS.len() {
+        let victim = PLAYERS[rng.next(PLAYERS.len())];
+        let attacker = PLAYERS[rng.next(PLAYERS.len())];
+        if rng.next(3) == 0 {
+            line(
+                t,
+                "LogSquadTrace",
+                format!(
+                    "[DedicatedServer]ASQSoldier::Wound(): Player:[TAG] {} KillingDamage=80.000000 from {} caused by BP_M4A1_C_2147",
+                    victim, attacker
+                ),
+            );
+        } else {
+            line(
+                t,
+                "LogSquad",
+                format!(
+                    "Player:[TAG] {} ActualDamage=40.000000 from {} caused by BP_M4A1_C_2147",
+                    victim, attacker
+                ),
+            );
+        }
+    }
+    line(
+        t,
+        "LogGameState",
+        String::from("Match State Changed from InProgress to WaitingPostMatch"),
+    );
+}
+
+fn main() {
+    let matches: usize = env::args()
+        .skip(1)
+        .filter_map(|a| a.parse().ok())
+        .next()
+        .unwrap_or(2000);
+
+    let mut rng = Lcg(42);
+    let mut t: u64 = 0;
+    let mut lines = Vec::with_capacity(matches * LINES_PER_MATCH);
+    for _i in 0..matches {
+        synthetic_match(&mut rng, &mut t, &mut lines);
+    }
+
+    let parser = Parser::new();
+    let parsed: Vec<_> = lines
+        .iter()
+        .filter_map(|l| parser.parse_line(l).expect("synthetic line should parse"))
+        .collect();
+
+    let window = LINES_PER_MATCH * 50;
+    let mut g = GameState::new();
+    let mut rates = Vec::new();
+    let start = Instant::now();
+    for chunk in parsed.chunks(window) {
+        let chunk_start = Instant::now();
+        for line in chunk {
+            apply_line(line, &mut g).expect("synthetic line should apply");
+        }
+        let secs = chunk_start.elapsed().as_secs_f64();
+        rates.push(chunk.len() as f64 / secs);
+    }
+    let total = start.elapsed();
+
+    println!(
+        "{} matches, {} lines applied in {:.3}s",
+        g.games.len(),
+        parsed.len(),
+        total.as_secs_f64()
+    );
+    if let (Some(first), Some(last)) = (rates.first(), rates.last()) {
+        println!(
+            "first {} lines: {:.0} lines/s, last {} lines: {:.0} lines/s",
+            window, first, window, last
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+use reader::{detect_compression, Compression};
+use state::{FileContext, GameState};
+
+// How much of the start of a file we hash to tell files apart. Squad logs
+// open with a header that has the server start time in it, so this is plenty.
+pub const PREFIX_LEN: u64 = 4096;
+
+// How far we got through one log file. A file is identified by its path and a
+// hash of its first bytes, so a fresh log written to the same path after a
+// server restart is a different file and gets processed from the top. A file
+// at a new path whose first bytes match is taken to be one we've read that was
+// renamed, as Squad does when it rotates its log.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FileCheckpoint {
+    pub path: String,
+    // The hash only covers bytes we have consumed, so a file that was still
+    // shorter than PREFIX_LEN the last time we saw it can still be matched.
+    pub prefix_len: u64,
+    pub prefix_hash: u64,
+    pub offset: u64,
+    // How many lines come before offset, so errors can say which line they
+    // were on.
+    #[serde(default)]
+    pub line: u64,
+    // The game the file was in at offset. Checkpoints from before we kept
+    // this have None.
+    #[serde(default)]
+    pub context: Option<FileContext>,
+}
+
+// FNV-1a. We only need something stable across builds, not something secure.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in bytes {
+        h ^= u64::from(*b);
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h
+}
+
+fn read_prefix(path: &Path, len: u64) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    File::open(path)?.take(len).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+fn path_key(path: &Path) -> String {
+    match fs::canonicalize(path) {
+        Ok(p) => p.to_string_lossy().into_owned(),
+        Err(_e) => path.to_string_lossy().into_owned(),
+    }
+}
+
+fn find_checkpoint(g: &GameState, path: &Path) -> io::Result<Option<usize>> {
+    // Offsets into compressed logs count decompressed bytes, so they can be
+    // bigger than the file.
+    let len = match detect_compression(path)? {
+        Compression::Plain => fs::metadata(path)?.len(),
+        _ => u64::MAX,
+    };
+    let prefix = read_prefix(path, PREFIX_LEN)?;
+    Ok(find_file(g, &path_key(path), len, &prefix))
+}
+
+// The checkpoint for a file len bytes long that starts with prefix, looked up
+// under key first.
+fn find_file(g: &GameState, key: &str, len: u64, prefix: &[u8]) -> Option<usize> {
+    let matches = |c: &FileCheckpoint| {
+        c.offset <= len
+            && c.prefix_len <= prefix.len() as u64
+            && hash_bytes(&prefix[..c.prefix_len as usize]) == c.prefix_hash
+    };
+    if let Some(i) = g.files.iter().position(|c| c.path == key && matches(c)) {
+        return Some(i);
+    }
+    // When Squad rotates its log the file we read is renamed, so a file we
+    // haven't seen at this path may still be one we've read. A checkpoint
+    // with nothing hashed would match any file, so those need the path.
+    g.files.iter().position(|c| c.prefix_len > 0 && matches(c))
+}
+
+// The byte offset to resume reading the file at path from. Files we haven't
+// seen before start at zero.
+pub fn resume_offset(g: &GameState, path: &Path) -> io::Result<u64> {
+    match find_checkpoint(g, path)? {
+        Some(i) => Ok(g.files[i].offset),
+        None => Ok(0),
+    }
+}
+
+// How many lines of the file at path come before resume_offset.
+pub fn resume_line(g: &GameState, path: &Path) -> io::Result<u64> {
+    match find_checkpoint(g, path)? {
+        Some(i) => Ok(g.files[i].line),
+        None => Ok(0),
+    }
+}
+
+// Put back the context the file at path was in when we stopped reading it,
+// so the rest of its lines go to its own game rather than to whichever log
+// was read last. A file we haven't seen starts in no game. Checkpoints from
+// before contexts were kept leave g's as it is, which is what we used to do.
+pub fn resume_context(g: &mut GameState, path: &Path) -> io::Result<()> {
+    let context = match find_checkpoint(g, path)? {
+        Some(i) => match &g.files[i].context {
+            Some(context) => context.clone(),
+            None => return Ok(()),
+        },
+        None => FileContext::default(),
+    };
+    g.enter_file(&context);
+    Ok(())
+}
+
+// Record that everything before offset in the file at path, which is line
+// lines in, has been applied to g. offset should sit on a line boundary.
+pub fn record_progress(g: &mut GameState, path: &Path, offset: u64, line: u64) -> io::Result<()> {
+    let found = find_checkpoint(g, path)?;
+    let prefix = read_prefix(path, PREFIX_LEN.min(offset))?;
+    record(g, path, found, &prefix, offset, line);
+    Ok(())
+}
+
+// Like record_progress, for a file that was at path but has since been
+// renamed or cut short, given its first bytes. There have to be at least
+// offset of them, or PREFIX_LEN if that's fewer.
+pub fn record_moved(g: &mut GameState, path: &Path, prefix: &[u8], offset: u64, line: u64) {
+    let prefix = &prefix[..PREFIX_LEN.min(offset) as usize];
+    let found = find_file(g, &path_key(path), u64::MAX, prefix);
+    record(g, path, found, prefix, offset, line);
+}
+
+fn record(
+    g: &mut GameState,
+    path: &Path,
+    found: Option<usize>,
+    prefix: &[u8],
+    offset: u64,
+    line: u64,
+) {
+    let checkpoint = FileCheckpoint {
+        path: path_key(path),
+        prefix_len: prefix.len() as u64,
+        prefix_hash: hash_bytes(prefix),
+        offset,
+        line,
+        context: Some(g.file_context()),
+    };
+    match found {
+        Some(i) => g.files[i] = checkpoint,
+        None => g.files.push(checkpoint),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse::Parser;
+    use state::apply_line;
+    use tempfile::tempdir;
+
+    const FIRST: &str = "[2026.10.01-10.00.00:000][  1]LogWorld: one\n";
+    const SECOND: &str = "[2026.10.01-10.00.01:000][  1]LogWorld: two\n";
+
+    fn read_all(g: &mut GameState, path: &Path, lines: u64) {
+        let len = fs::metadata(path).unwrap().len();
+        record_progress(g, path, len, lines).unwrap();
+    }
+
+    // Apply what's new in the log at path, as ingest does.
+    fn ingest(g: &mut GameState, path: &Path) {
+        resume_context(g, path).unwrap();
+        let offset = resume_offset(g, path).unwrap();
+        let mut line = resume_line(g, path).unwrap();
+        let text = fs::read_to_string(path).unwrap();
+        let parser = Parser::new();
+        for l in text[offset as usize..].lines() {
+            if let Some(parsed) = parser.parse_line(l).unwrap() {
+                apply_line(&parsed, g).unwrap();
+            }
+            line += 1;
+        }
+        record_progress(g, path, text.len() as u64, line).unwrap();
+    }
+
+    // A map loading, with shooter and victim connecting and spawning.
+    fn game_start(t: &str, map: &str, shooter: &str, victim: &str) -> String {
+        let mut log = format!(
+            "[{}][  1]LogWorld: StartLoadingDestination to: /Game/Maps/{}\n",
+            t, map
+        );
+        for name in &[shooter, victim] {
+            log += &format!(
+                "[{}][  1]LogSquadTrace: [DedicatedServer]ASQPlayerController::ChangeState(): PC={} OldState=Inactive NewState=Playing\n",
+                t, name
+            );
+            log += &format!(
+                "[{}][  1]LogSquadTrace: [DedicatedServer]ASQPlayerController::SetCurrentRole(): On Server PC={} NewRole=USA_Rifleman_01\n",
+                t, name
+            );
+        }
+        log
+    }
+
+    fn wound(t: &str, shooter: &str, victim: &str) -> String {
+        format!(
+            "[{t}][  1]LogSquad: Player:{v} ActualDamage=100.000000 from {s} caused by BP_M4_C_1\n\
+             [{t}][  1]LogSquadTrace: [DedicatedServer]ASQSoldier::Wound(): Player:{v} KillingDamage=100.000000 from {s} caused by BP_M4_C_1\n",
+            t = t,
+            s = shooter,
+            v = victim
+        )
+    }
+
+    #[test]
+    fn resumes_where_it_left_off() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("SquadGame.log");
+        let mut g = GameState::new();
+        fs::write(&path, FIRST).unwrap();
+        assert_eq!(resume_offset(&g, &path).unwrap(), 0);
+
+        read_all(&mut g, &path, 1);
+        fs::write(&path, format!("{}{}", FIRST, SECOND)).unwrap();
+        assert_eq!(resume_offset(&g, &path).unwrap(), FIRST.len() as u64);
+        assert_eq!(resume_line(&g, &path).unwrap(), 1);
+
+        read_all(&mut g, &path, 2);
+        assert_eq!(g.files.len(), 1);
+        assert_eq!(resume_line(&g, &path).unwrap(), 2);
+    }
+
+    #[test]
+    fn starts_over_on_a_truncated_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("SquadGame.log");
+        let mut g = GameState::new();
+        fs::write(&path, format!("{}{}", FIRST, SECOND)).unwrap();
+        read_all(&mut g, &path, 2);
+
+        // Shorter than where we got to.
+        fs::write(&path, SECOND).unwrap();
+        assert_eq!(resume_offset(&g, &path).unwrap(), 0);
+        assert_eq!(resume_line(&g, &path).unwrap(), 0);
+
+        // Long enough, but not the file we read.
+        fs::write(&path, format!("{}{}{}", SECOND, FIRST, FIRST)).unwrap();
+        assert_eq!(resume_offset(&g, &path).unwrap(), 0);
+    }
+
+    #[test]
+    fn follows_a_rotated_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("SquadGame.log");
+        let rotated = dir.path().join("SquadGame-backup-2026.10.01-11.00.00.log");
+        let mut g = GameState::new();
+        fs::write(&path, FIRST).unwrap();
+        read_all(&mut g, &path, 1);
+
+        fs::rename(&path, &rotated).unwrap();
+        fs::write(&path, SECOND).unwrap();
+        assert_eq!(resume_offset(&g, &rotated).unwrap(), FIRST.len() as u64);
+        assert_eq!(resume_line(&g, &rotated).unwrap(), 1);
+        assert_eq!(resume_offset(&g, &path).unwrap(), 0);
+
+        // Both are tracked from here on.
+        read_all(&mut g, &rotated, 1);
+        read_all(&mut g, &path, 1);
+        assert_eq!(g.files.len(), 2);
+        assert_eq!(resume_offset(&g, &rotated).unwrap(), FIRST.len() as u64);
+        assert_eq!(resume_offset(&g, &path).unwrap(), SECOND.len() as u64);
+    }
+
+    #[test]
+    fn remembers_how_far_a_followed_log_got_after_its_gone() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("SquadGame.log");
+        let rotated = dir.path().join("SquadGame-backup-2026.10.01-11.00.00.log");
+        let mut g = GameState::new();
+        fs::write(&path, FIRST).unwrap();
+        record_progress(&mut g, &path, 0, 0).unwrap();
+
+        // Following it got through FIRST, then it was moved away.
+        fs::rename(&path, &rotated).unwrap();
+        fs::write(&path, SECOND).unwrap();
+        record_moved(&mut g, &path, FIRST.as_bytes(), FIRST.len() as u64, 1);
+
+        assert_eq!(g.files.len(), 1);
+        assert_eq!(resume_offset(&g, &rotated).unwrap(), FIRST.len() as u64);
+        assert_eq!(resume_line(&g, &rotated).unwrap(), 1);
+        assert_eq!(resume_offset(&g, &path).unwrap(), 0);
+    }
+
+    #[test]
+    fn each_log_carries_on_in_its_own_game() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.log");
+        let b = dir.path().join("b.log");
+        let mut g = GameState::new();
+
+        // Two servers, each partway through a game, read one after the other.
+        let mut log_a = game_start("2026.10.01-10.00.00:000", "Narva", "Alice", "Bob");
+        let mut log_b = game_start("2026.10.01-10.05.00:000", "Gorodok", "Carol", "Dave");
+        fs::write(&a, &log_a).unwrap();
+        fs::write(&b, &log_b).unwrap();
+        ingest(&mut g, &a);
+        ingest(&mut g, &b);
+
+        // In the next run, the first server's lines are read while the state
+        // is still in the second server's game.
+        log_a += &wound("2026.10.01-10.10.00:000", "Alice", "Bob");
+        log_b += &wound("2026.10.01-10.11.00:000", "Carol", "Dave");
+        log_b += "[2026.10.01-10.20.00:000][  1]LogGameState: Match State Changed from InProgress to WaitingPostMatch\n";
+        fs::write(&a, &log_a).unwrap();
+        fs::write(&b, &log_b).unwrap();
+        ingest(&mut g, &a);
+        ingest(&mut g, &b);
+
+        assert_eq!(g.games.len(), 2);
+        let (narva, gorodok) = (&g.games[0], &g.games[1]);
+        assert_eq!(narva.players["Bob"].players_killed_by["Alice"], 1);
+        assert_eq!(gorodok.players["Dave"].players_killed_by["Carol"], 1);
+        assert!(narva.end_time.is_none());
+        assert!(gorodok.end_time.is_some());
+    }
+
+    #[test]
+    fn an_empty_checkpoint_only_matches_its_own_path() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.log");
+        let b = dir.path().join("b.log");
+        let mut g = GameState::new();
+        fs::write(&a, FIRST).unwrap();
+        fs::write(&b, FIRST).unwrap();
+        record_progress(&mut g, &a, 0, 0).unwrap();
+        record_progress(&mut g, &b, FIRST.len() as u64, 1).unwrap();
+
+        assert_eq!(g.files.len(), 2);
+        assert_eq!(resume_offset(&g, &a).unwrap(), 0);
+    }
+}
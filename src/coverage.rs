@@ -0,0 +1,191 @@
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use event::SquadEvent;
+use parse::Parser;
+
+// Past this many distinct shapes, new ones are only counted in
+// other_shapes, so a log full of unmaskable text can't eat all our memory.
+const MAX_SHAPES: usize = 10000;
+
+#[derive(Debug, Clone, Default)]
+pub struct CategoryCoverage {
+    // Whether the parser has any patterns for this category.
+    pub handled: bool,
+    pub lines: u64,
+    // Lines that produced at least one event.
+    pub matched: u64,
+}
+
+// How much of a log the parser understands. Squad updates tend to change the
+// wording of log lines, which shows up here as a category's matched count
+// dropping and a new shape near the top of the unmatched list.
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    pub categories: BTreeMap<String, CategoryCoverage>,
+    // Unmatched messages in the categories we have patterns for, with
+    // numbers and names masked, and how often each was seen.
+    pub unmatched: HashMap<String, u64>,
+    pub other_shapes: u64,
+    // Lines with no timestamp and category, or a timestamp that didn't parse.
+    pub unparsed: u64,
+    names: HashSet<String>,
+    online_ids: Regex,
+    key_value: Regex,
+    number: Regex,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage {
+            categories: BTreeMap::new(),
+            unmatched: HashMap::new(),
+            other_shapes: 0,
+            unparsed: 0,
+            names: HashSet::new(),
+            online_ids: Regex::new(r"\(Online IDs:[^)]*\)").unwrap(),
+            key_value: Regex::new(r"\b(\w+)([=:])[^\s:=][^\s=]*").unwrap(),
+            number: Regex::new(r"\d+(\.\d+)?").unwrap(),
+        }
+    }
+
+    // Remember the names in an event, so they can be masked out of the lines
+    // we don't understand.
+    fn learn_names(&mut self, event: &SquadEvent) {
+        let names: Vec<&String> = match event {
+            SquadEvent::RoleChanged { player, .. } => vec![player],
+            SquadEvent::Damaged {
+                victim, attacker, ..
+            }
+            | SquadEvent::Wounded {
+                victim, attacker, ..
+            }
+            | SquadEvent::Died {
+                victim, attacker, ..
+            } => vec![victim, attacker],
+            SquadEvent::Revived {
+                reviver, revivee, ..
+            } => vec![reviver, revivee],
+            SquadEvent::ControllerStateChanged { controller, .. } => vec![controller],
+            SquadEvent::PlayerJoined { name, .. } | SquadEvent::PlayerIdentified { name, .. } => {
+                vec![name]
+            }
+            _ => Vec::new(),
+        };
+        for name in names {
+            for word in name.split_whitespace() {
+                self.names.insert(String::from(word));
+            }
+        }
+    }
+
+    // The shape of a message: online IDs, the values of key=value and
+    // key:value pairs, numbers and any word of a player name we know are
+    // replaced with placeholders.
+    pub fn shape(&self, msg: &str) -> String {
+        let msg = self.online_ids.replace_all(msg, "(Online IDs: *)");
+        let msg = self.key_value.replace_all(&msg, "$1$2*");
+        let msg = self.number.replace_all(&msg, "#");
+        let words: Vec<&str> = msg
+            .split(' ')
+            .map(|w| if self.names.contains(w) { "<name>" } else { w })
+            .collect();
+        words.join(" ")
+    }
+
+    pub fn add_line(&mut self, parser: &Parser, line: &str) {
+        let (category, msg) = match parser.split_line(line) {
+            Some(t) => t,
+            None => {
+                self.unparsed += 1;
+                return;
+            }
+        };
+        let parsed = match parser.parse_line(line) {
+            Ok(Some(parsed)) => parsed,
+            _ => {
+                self.unparsed += 1;
+                return;
+            }
+        };
+
+        let c = self.categories.entry(String::from(category)).or_default();
+        c.handled = parser.handles(category);
+        c.lines += 1;
+        if !parsed.events.is_empty() {
+            c.matched += 1;
+            for event in &parsed.events {
+                self.learn_names(event);
+            }
+            return;
+        }
+
+        if !c.handled {
+            return;
+        }
+        let shape = format!("{}: {}", category, self.shape(msg));
+        if self.unmatched.len() < MAX_SHAPES || self.unmatched.contains_key(&shape) {
+            *self.unmatched.entry(shape).or_insert(0) += 1;
+        } else {
+            self.other_shapes += 1;
+        }
+    }
+
+    // The n most common unmatched shapes, most common first.
+    pub fn top_unmatched(&self, n: usize) -> Vec<(&String, u64)> {
+        let mut shapes: Vec<(&String, u64)> = self.unmatched.iter().map(|(s, c)| (s, *c)).collect();
+        shapes.sort_by(|(a, ac), (b, bc)| bc.cmp(ac).then(a.cmp(b)));
+        shapes.truncate(n);
+        shapes
+    }
+
+    // A plain text report, for reading in a terminal.
+    pub fn report(&self, n: usize) -> String {
+        let mut out = String::new();
+        out.push_str(&format!(
+            "{:<24} {:>12} {:>12} {:>8}\n",
+            "category", "lines", "matched", "%"
+        ));
+        for (category, c) in &self.categories {
+            if !c.handled && c.matched == 0 {
+                continue;
+            }
+            out.push_str(&format!(
+                "{:<24} {:>12} {:>12} {:>7.1}%\n",
+                category,
+                c.lines,
+                c.matched,
+                100.0 * c.matched as f64 / c.lines as f64
+            ));
+        }
+        let other: u64 = self
+            .categories
+            .iter()
+            .filter(|(_category, c)| !c.handled && c.matched == 0)
+            .map(|(_category, c)| c.lines)
+            .sum();
+        out.push_str(&format!("{:<24} {:>12}\n", "(other categories)", other));
+        out.push_str(&format!(
+            "{:<24} {:>12}\n",
+            "(not log lines)", self.unparsed
+        ));
+
+        out.push_str(&format!("\ntop {} unmatched shapes:\n", n));
+        for (shape, count) in self.top_unmatched(n) {
+            out.push_str(&format!("{:>12}  {}\n", count, shape));
+        }
+        if self.other_shapes > 0 {
+            out.push_str(&format!(
+                "{:>12}  (lines in shapes past the first {})\n",
+                self.other_shapes, MAX_SHAPES
+            ));
+        }
+        out
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Coverage {
+        Coverage::new()
+    }
+}
@@ -0,0 +1,46 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+
+// Why a line couldn't be parsed or applied to the game state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogError {
+    // The line looked like a log line, but its timestamp didn't parse.
+    BadTimestamp(String),
+    // Something happened to a player before any map was loaded.
+    NoGame,
+    // A name we couldn't match to anyone who has connected.
+    UnknownPlayer(String),
+    // A player who hasn't spawned in the current game.
+    NotSpawned(String),
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogError::BadTimestamp(t) => write!(f, "bad timestamp {:?}", t),
+            LogError::NoGame => write!(f, "event before any map was loaded"),
+            LogError::UnknownPlayer(n) => write!(f, "no connected player matches {:?}", n),
+            LogError::NotSpawned(n) => write!(f, "{:?} has not spawned in this game", n),
+        }
+    }
+}
+
+impl Error for LogError {}
+
+// A LogError, and where in which file it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineError {
+    pub path: PathBuf,
+    // Counting from 1.
+    pub line: u64,
+    pub error: LogError,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.error)
+    }
+}
+
+impl Error for LineError {}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+use identity::OnlineIds;
+
+// A single thing that happened in a Squad server log. Each variant carries the
+// timestamp of the line it was parsed from. Names and roles are recorded
+// exactly as they appear in the log, including the "nullptr" placeholder the
+// server prints when it doesn't know who was involved.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum SquadEvent {
+    // A player controller picked a new role (kit), which is also how we learn
+    // that a player has spawned.
+    RoleChanged {
+        timestamp: DateTime<FixedOffset>,
+        player: String,
+        role: String,
+    },
+    // A player took damage from another player. The damage is as the server
+    // logged it, sign and all; newer logs sometimes print it negative.
+    Damaged {
+        timestamp: DateTime<FixedOffset>,
+        victim: String,
+        damage: f32,
+        attacker: String,
+        weapon: String,
+    },
+    // A player was knocked down. The attacker is printed as their
+    // controller, not their name.
+    Wounded {
+        timestamp: DateTime<FixedOffset>,
+        victim: String,
+        damage: f32,
+        attacker: String,
+        weapon: String,
+    },
+    // A player died, either finished off or giving up while down. The
+    // attacker is printed as in Wounded.
+    Died {
+        timestamp: DateTime<FixedOffset>,
+        victim: String,
+        damage: f32,
+        attacker: String,
+        weapon: String,
+    },
+    // One player revived another.
+    Revived {
+        timestamp: DateTime<FixedOffset>,
+        reviver: String,
+        revivee: String,
+    },
+    // The match moved between states, e.g. InProgress to WaitingPostMatch.
+    MatchStateChanged {
+        timestamp: DateTime<FixedOffset>,
+        from: String,
+        to: String,
+    },
+    // The server started loading a new map.
+    MapLoading {
+        timestamp: DateTime<FixedOffset>,
+        map: String,
+    },
+    // A player controller changed state. Controller names are the full names
+    // players connect with, so these are what we resolve damage and downs
+    // against.
+    ControllerStateChanged {
+        timestamp: DateTime<FixedOffset>,
+        controller: String,
+        old_state: String,
+        new_state: String,
+    },
+    // A player controller logged in with these online IDs. The player's name
+    // follows in a PlayerJoined.
+    PlayerLoggedIn {
+        timestamp: DateTime<FixedOffset>,
+        controller: String,
+        ids: OnlineIds,
+    },
+    // The player who last logged in finished joining under this name.
+    PlayerJoined {
+        timestamp: DateTime<FixedOffset>,
+        name: String,
+    },
+    // A player name was printed together with its online IDs. Newer logs do
+    // this on most controller, damage and wound lines.
+    PlayerIdentified {
+        timestamp: DateTime<FixedOffset>,
+        name: String,
+        ids: OnlineIds,
+    },
+}
+
+impl SquadEvent {
+    pub fn timestamp(&self) -> DateTime<FixedOffset> {
+        match self {
+            SquadEvent::RoleChanged { timestamp, .. }
+            | SquadEvent::Damaged { timestamp, .. }
+            | SquadEvent::Wounded { timestamp, .. }
+            | SquadEvent::Died { timestamp, .. }
+            | SquadEvent::Revived { timestamp, .. }
+            | SquadEvent::MatchStateChanged { timestamp, .. }
+            | SquadEvent::MapLoading { timestamp, .. }
+            | SquadEvent::ControllerStateChanged { timestamp, .. }
+            | SquadEvent::PlayerLoggedIn { timestamp, .. }
+            | SquadEvent::PlayerJoined { timestamp, .. }
+            | SquadEvent::PlayerIdentified { timestamp, .. } => *timestamp,
+        }
+    }
+}
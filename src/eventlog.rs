@@ -0,0 +1,310 @@
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use checkpoint::hash_bytes;
+use event::SquadEvent;
+use parse::ParsedLine;
+use state::{apply_line, FileContext, GameState};
+
+// Events are compressed a frame at a time, once this much has been recorded.
+const FRAME_BYTES: usize = 1 << 20;
+// How much of the end of the committed log tail_hash covers. Frames are
+// compressed, so this is plenty to tell two histories apart.
+const TAIL_BYTES: u64 = 4096;
+
+// One line of the event log.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum EventRecord {
+    // Starts the lines from one log, with what we knew about that log when
+    // we read it, and the game it was in. Logs recorded before we kept the
+    // context have None, and carry on in whatever game came before.
+    File {
+        path: PathBuf,
+        generation: Option<String>,
+        zone: Option<Tz>,
+        #[serde(default)]
+        context: Option<FileContext>,
+    },
+    // The events of one log line, whether or not they applied, so lines
+    // that failed under the rules of the time can be tried again on rebuild.
+    Line {
+        timestamp: DateTime<FixedOffset>,
+        events: Vec<SquadEvent>,
+    },
+}
+
+fn invalid_events(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Every event a statefile was built from, so it can be rebuilt when the rules
+// for applying them change, without the original logs. The log is JSON lines
+// in a run of zstd frames, and is only ever appended to. The statefile
+// records how much of it is committed; anything past that is from a run whose
+// state never got saved, and is dropped when the log is next opened.
+pub struct EventLog {
+    file: File,
+    pending: Vec<u8>,
+    // The File record for the log being read, held back until it has a line
+    // worth recording.
+    next_file: Option<EventRecord>,
+}
+
+impl EventLog {
+    // Open the event log at path to add to it, where len is the length the
+    // statefile committed.
+    pub fn open(path: &Path, len: u64) -> io::Result<EventLog> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)?;
+        let on_disk = file.metadata()?.len();
+        if on_disk < len {
+            return Err(invalid_events(format!(
+                "{} is {} bytes, but the statefile expects {}",
+                path.display(),
+                on_disk,
+                len
+            )));
+        }
+        file.set_len(len)?;
+        let mut log = EventLog {
+            file,
+            pending: Vec::new(),
+            next_file: None,
+        };
+        log.file.seek(SeekFrom::End(0))?;
+        Ok(log)
+    }
+
+    // The lines recorded from now on are from the log at path, which g is
+    // set up to apply.
+    pub fn start_file(&mut self, path: &Path, g: &GameState) {
+        self.next_file = Some(EventRecord::File {
+            path: path.to_path_buf(),
+            generation: g.generation.clone(),
+            zone: g.source_timezone,
+            context: Some(g.file_context()),
+        });
+    }
+
+    // Record the events of a line. Lines with no events are left out.
+    pub fn record_line(
+        &mut self,
+        timestamp: DateTime<FixedOffset>,
+        events: &[SquadEvent],
+    ) -> io::Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        if let Some(file) = self.next_file.take() {
+            self.record(&file)?;
+        }
+        self.record(&EventRecord::Line {
+            timestamp,
+            events: events.to_vec(),
+        })
+    }
+
+    fn record(&mut self, record: &EventRecord) -> io::Result<()> {
+        serde_json::to_writer(&mut self.pending, record)?;
+        self.pending.push(b'\n');
+        if self.pending.len() >= FRAME_BYTES {
+            self.write_frame()?;
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let frame = zstd::encode_all(&self.pending[..], 0)?;
+        self.file.write_all(&frame)?;
+        self.pending.clear();
+        Ok(())
+    }
+
+    // Write out everything recorded so far and get it onto disk. Returns the
+    // length for the statefile to commit, which it must save before the log
+    // counts as written.
+    pub fn commit(&mut self) -> io::Result<u64> {
+        self.write_frame()?;
+        self.file.sync_all()?;
+        self.file.stream_position()
+    }
+}
+
+// A hash of the end of the first len bytes of the event log at path, or None
+// if the log is shorter than that. It changes if the log is ever cut back
+// below len and added to again.
+pub fn tail_hash(path: &Path, len: u64) -> io::Result<Option<u64>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound && len == 0 => {
+            return Ok(Some(hash_bytes(&[])))
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if file.metadata()?.len() < len {
+        return Ok(None);
+    }
+    let start = len.saturating_sub(TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut tail = Vec::new();
+    file.take(len - start).read_to_end(&mut tail)?;
+    Ok(Some(hash_bytes(&tail)))
+}
+
+// Read the first len bytes of the event log at path, handing each record to f
+// in order.
+pub fn read_events<F>(path: &Path, len: u64, mut f: F) -> io::Result<()>
+where
+    F: FnMut(EventRecord),
+{
+    if len == 0 {
+        return Ok(());
+    }
+    let file = File::open(path)?;
+    let decoder = zstd::Decoder::new(file.take(len))?;
+    for line in BufReader::new(decoder).lines() {
+        let record = serde_json::from_str(&line?).map_err(|e| invalid_events(e.to_string()))?;
+        f(record);
+    }
+    Ok(())
+}
+
+// A fresh GameState made by applying every event behind old, as saved in the
+// event log at path, under the current rules. What the events don't tell us,
+// like how far into each log we've read, is kept from old. Returns the new
+// state and how many lines no longer apply.
+pub fn rebuild(old: &GameState, path: &Path) -> io::Result<(GameState, u64)> {
+    if !old.events_complete {
+        return Err(invalid_events(String::from(
+            "the event log starts partway through this statefile's history, \
+             so it can't be rebuilt from it",
+        )));
+    }
+
+    let mut g = GameState::new();
+    g.overrides = old.overrides.clone();
+    g.timezones = old.timezones.clone();
+    let mut failed = 0;
+    read_events(path, old.events_len, |record| match record {
+        EventRecord::File {
+            generation,
+            zone,
+            context,
+            ..
+        } => {
+            g.generation = generation;
+            g.source_timezone = zone;
+            if let Some(context) = context {
+                g.enter_file(&context);
+            }
+        }
+        EventRecord::Line { timestamp, events } => {
+            if apply_line(&ParsedLine { timestamp, events }, &mut g).is_err() {
+                failed += 1;
+            }
+        }
+    })?;
+
+    g.files = old.files.clone();
+    if old.last_timestamp > g.last_timestamp {
+        g.last_timestamp = old.last_timestamp;
+    }
+    g.events_len = old.events_len;
+    g.events_tail = old.events_tail;
+    Ok((g, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse::Parser;
+    use std::fs;
+    use tempfile::tempdir;
+
+    const LOG: &str = "\
+[2026.10.01-10.00.00:000][  1]LogWorld: StartLoadingDestination to: /Game/Maps/Narva
+[2026.10.01-10.00.01:000][  1]LogSquadTrace: [DedicatedServer]ASQPlayerController::ChangeState(): PC=Alice OldState=Inactive NewState=Playing
+[2026.10.01-10.00.01:000][  1]LogSquadTrace: [DedicatedServer]ASQPlayerController::ChangeState(): PC=Bob OldState=Inactive NewState=Playing
+[2026.10.01-10.00.02:000][  1]LogSquadTrace: [DedicatedServer]ASQPlayerController::SetCurrentRole(): On Server PC=Alice NewRole=USA_Rifleman_01
+[2026.10.01-10.00.02:000][  1]LogSquadTrace: [DedicatedServer]ASQPlayerController::SetCurrentRole(): On Server PC=Bob NewRole=USA_Medic_01
+[2026.10.01-10.01.00:000][  1]LogSquad: Player:Bob ActualDamage=100.000000 from Alice caused by BP_M4_C_1
+[2026.10.01-10.01.00:000][  1]LogSquadTrace: [DedicatedServer]ASQSoldier::Wound(): Player:Bob KillingDamage=100.000000 from Alice caused by BP_M4_C_1
+[2026.10.01-10.02.00:000][  1]LogSquad: Alice has revived Bob.
+[2026.10.01-10.30.00:000][  1]LogGameState: Match State Changed from InProgress to WaitingPostMatch
+";
+
+    // Apply lines to g and record them in events, as ingest does.
+    fn ingest(lines: &str, g: &mut GameState, events: &mut EventLog) {
+        let parser = Parser::new();
+        events.start_file(Path::new("SquadGame.log"), g);
+        for line in lines.lines() {
+            let parsed = parser.parse_line(line).unwrap().unwrap();
+            apply_line(&parsed, g).unwrap();
+            events
+                .record_line(parsed.timestamp, &parsed.events)
+                .unwrap();
+        }
+    }
+
+    fn count_lines(path: &Path, len: u64) -> usize {
+        let mut lines = 0;
+        read_events(path, len, |record| {
+            if let EventRecord::Line { .. } = record {
+                lines += 1;
+            }
+        })
+        .unwrap();
+        lines
+    }
+
+    #[test]
+    fn drops_what_was_never_committed() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state.json.events");
+        let mut g = GameState::new();
+        let (first, rest) = LOG.split_at(LOG.find("[2026.10.01-10.01.00").unwrap());
+
+        let mut events = EventLog::open(&path, 0).unwrap();
+        ingest(first, &mut g, &mut events);
+        let committed = events.commit().unwrap();
+        // This run writes more, but its statefile is never saved.
+        ingest(rest, &mut g, &mut events);
+        events.commit().unwrap();
+        drop(events);
+        assert_eq!(count_lines(&path, fs::metadata(&path).unwrap().len()), 9);
+
+        EventLog::open(&path, committed).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), committed);
+        assert_eq!(count_lines(&path, committed), 5);
+    }
+
+    #[test]
+    fn rebuilding_gives_back_the_same_state() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state.json.events");
+        let mut g = GameState::new();
+        let mut events = EventLog::open(&path, 0).unwrap();
+        ingest(LOG, &mut g, &mut events);
+        g.events_len = events.commit().unwrap();
+        g.events_tail = tail_hash(&path, g.events_len).unwrap();
+
+        let (rebuilt, failed) = rebuild(&g, &path).unwrap();
+        assert_eq!(failed, 0);
+        assert_eq!(
+            serde_json::to_value(&rebuilt).unwrap(),
+            serde_json::to_value(&g).unwrap()
+        );
+    }
+}
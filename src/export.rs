@@ -0,0 +1,326 @@
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use report::match_report;
+use state::GameState;
+use stats::lifetime_stats;
+use weapons::{WeaponCatalog, WeaponCategory};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ExportFormat, String> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!(
+                "unknown format {}, expected csv, ndjson or json",
+                s
+            )),
+        }
+    }
+}
+
+// A player's lifetime counters. Lists are joined with ", " so every column is
+// a single value.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerRow {
+    pub player: String,
+    pub identity: Option<String>,
+    pub aliases: String,
+    pub count_kills: u32,
+    pub count_killed: u32,
+    pub count_revives: u32,
+    pub count_revived: u32,
+    pub count_downs: u32,
+    pub median_time_to_revive: Option<f64>,
+    pub revive_rate_per_down: Option<f64>,
+    pub classes: String,
+}
+
+// How many times killer put victim down, over every game.
+#[derive(Debug, Clone, Serialize)]
+pub struct KillRow {
+    pub killer: String,
+    pub victim: String,
+    pub count: u32,
+}
+
+// One player's scoreboard line in one game, under the name they played it as.
+#[derive(Debug, Clone, Serialize)]
+pub struct GameRow {
+    pub start_time: DateTime<FixedOffset>,
+    pub map: String,
+    pub player: String,
+    pub identity: Option<String>,
+    pub downs_inflicted: u32,
+    pub downs_suffered: u32,
+    pub deaths: u32,
+    pub revives: u32,
+    pub damage_dealt: f32,
+    pub classes: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WeaponRow {
+    pub player: String,
+    pub weapon: String,
+    pub name: String,
+    pub category: WeaponCategory,
+    pub damage_dealt: f32,
+    pub downs_inflicted: u32,
+    pub downs_suffered: u32,
+}
+
+// The stats in a GameState as flat tables. Players are in name order, with
+// their kills by victim and their weapons most lethal first; games are in the
+// order they were played, with the scoreboard order of their match reports.
+pub struct Export {
+    pub players: Vec<PlayerRow>,
+    pub kills: Vec<KillRow>,
+    pub games: Vec<GameRow>,
+    pub weapons: Vec<WeaponRow>,
+}
+
+pub fn export_tables(g: &GameState, catalog: &WeaponCatalog) -> Export {
+    let mut lifetime: Vec<_> = lifetime_stats(g, catalog).into_iter().collect();
+    lifetime.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut export = Export {
+        players: Vec::new(),
+        kills: Vec::new(),
+        games: Vec::new(),
+        weapons: Vec::new(),
+    };
+    for (label, p) in lifetime {
+        let mut classes: Vec<String> = p.classes.iter().cloned().collect();
+        classes.sort();
+        let aliases: Vec<&str> = p.aliases.iter().map(|a| a.name.as_str()).collect();
+        export.players.push(PlayerRow {
+            player: label.clone(),
+            identity: p.identity.clone(),
+            aliases: aliases.join(", "),
+            count_kills: p.count_kills,
+            count_killed: p.count_killed,
+            count_revives: p.count_revives,
+            count_revived: p.count_revived,
+            count_downs: p.count_downs,
+            median_time_to_revive: p.median_time_to_revive,
+            revive_rate_per_down: p.revive_rate_per_down,
+            classes: classes.join(", "),
+        });
+
+        let mut kills: Vec<(&String, &u32)> = p.kills.iter().collect();
+        kills.sort();
+        for (victim, count) in kills {
+            export.kills.push(KillRow {
+                killer: label.clone(),
+                victim: victim.clone(),
+                count: *count,
+            });
+        }
+
+        for w in &p.weapons {
+            export.weapons.push(WeaponRow {
+                player: label.clone(),
+                weapon: w.weapon.clone(),
+                name: w.name.clone(),
+                category: w.category,
+                damage_dealt: w.damage_dealt,
+                downs_inflicted: w.downs_inflicted,
+                downs_suffered: w.downs_suffered,
+            });
+        }
+    }
+
+    for game in &g.games {
+        let report = match_report(g, game);
+        for p in report.players {
+            export.games.push(GameRow {
+                start_time: report.start_time,
+                map: report.map.clone(),
+                player: p.name,
+                identity: p.identity,
+                downs_inflicted: p.downs_inflicted,
+                downs_suffered: p.downs_suffered,
+                deaths: p.deaths,
+                revives: p.revives,
+                damage_dealt: p.damage_dealt,
+                classes: p.classes.join(", "),
+            });
+        }
+    }
+
+    export
+}
+
+fn write_table<T: Serialize>(path: &Path, rows: &[T], format: ExportFormat) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Csv => {
+            let mut w = csv::Writer::from_writer(out);
+            for row in rows {
+                w.serialize(row)?;
+            }
+            w.flush()?;
+            return Ok(());
+        }
+        ExportFormat::Ndjson => {
+            for row in rows {
+                serde_json::to_writer(&mut out, row)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, rows)?;
+            out.write_all(b"\n")?;
+        }
+    }
+    out.flush()
+}
+
+// Write each table of export into dir as players, kills, games and weapons,
+// returning the paths written.
+pub fn write_export(dir: &Path, export: &Export, format: ExportFormat) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let path = |table: &str| dir.join(format!("{}.{}", table, format.extension()));
+    let paths = vec![
+        path("players"),
+        path("kills"),
+        path("games"),
+        path("weapons"),
+    ];
+    write_table(&paths[0], &export.players, format)?;
+    write_table(&paths[1], &export.kills, format)?;
+    write_table(&paths[2], &export.games, format)?;
+    write_table(&paths[3], &export.weapons, format)?;
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event::SquadEvent;
+    use parse::get_dt;
+    use state::apply_event;
+    use tempfile::tempdir;
+
+    // Alice putting Bob down once on Narva.
+    fn one_down() -> GameState {
+        let t = get_dt("2026.10.01-10.00.00:000").unwrap();
+        let mut events = vec![SquadEvent::MapLoading {
+            timestamp: t,
+            map: String::from("Narva"),
+        }];
+        for name in &["Alice", "Bob"] {
+            events.push(SquadEvent::ControllerStateChanged {
+                timestamp: t,
+                controller: String::from(*name),
+                old_state: String::from("Inactive"),
+                new_state: String::from("Playing"),
+            });
+            events.push(SquadEvent::RoleChanged {
+                timestamp: t,
+                player: String::from(*name),
+                role: String::from("USA_Rifleman_01"),
+            });
+        }
+        for damage in &[40.0, 60.0] {
+            events.push(SquadEvent::Damaged {
+                timestamp: t,
+                victim: String::from("Bob"),
+                damage: *damage,
+                attacker: String::from("Alice"),
+                weapon: String::from("BP_M249_C_7"),
+            });
+        }
+        events.push(SquadEvent::Wounded {
+            timestamp: t,
+            victim: String::from("Bob"),
+            damage: 60.0,
+            attacker: String::from("Alice"),
+            weapon: String::from("BP_M249_C_7"),
+        });
+
+        let mut g = GameState::new();
+        for event in &events {
+            apply_event(event, &mut g).unwrap();
+        }
+        g
+    }
+
+    #[test]
+    fn writes_one_column_per_field_as_csv() {
+        let dir = tempdir().unwrap();
+        let export = export_tables(&one_down(), &WeaponCatalog::builtin());
+        write_export(dir.path(), &export, ExportFormat::Csv).unwrap();
+
+        let games = fs::read_to_string(dir.path().join("games.csv")).unwrap();
+        let lines: Vec<&str> = games.lines().collect();
+        assert_eq!(
+            lines[0],
+            "start_time,map,player,identity,downs_inflicted,downs_suffered,deaths,revives,damage_dealt,classes"
+        );
+        assert_eq!(
+            lines[1],
+            "2026-10-01T10:00:00Z,Narva,Alice,,1,0,0,0,100.0,USA_Rifleman_01"
+        );
+        assert_eq!(lines.len(), 3);
+
+        let weapons = fs::read_to_string(dir.path().join("weapons.csv")).unwrap();
+        assert_eq!(
+            weapons.lines().collect::<Vec<_>>(),
+            vec![
+                "player,weapon,name,category,damage_dealt,downs_inflicted,downs_suffered",
+                "Alice,BP_M249_C,M249,lmg,100.0,1,0",
+                "Bob,BP_M249_C,M249,lmg,0.0,0,1",
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_one_object_per_line_as_ndjson() {
+        let dir = tempdir().unwrap();
+        let export = export_tables(&one_down(), &WeaponCatalog::builtin());
+        write_export(dir.path(), &export, ExportFormat::Ndjson).unwrap();
+
+        let kills = fs::read_to_string(dir.path().join("kills.ndjson")).unwrap();
+        assert_eq!(
+            kills,
+            "{\"killer\":\"Alice\",\"victim\":\"Bob\",\"count\":1}\n"
+        );
+
+        let players = fs::read_to_string(dir.path().join("players.ndjson")).unwrap();
+        let rows: Vec<serde_json::Value> = players
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1]["player"], "Bob");
+        assert_eq!(rows[1]["count_killed"], 1);
+        assert_eq!(rows[1]["count_downs"], 1);
+        assert!(rows[1]["median_time_to_revive"].is_null());
+    }
+}
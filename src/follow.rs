@@ -0,0 +1,263 @@
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use checkpoint::PREFIX_LEN;
+use reader::{decode_line, LogLine};
+
+// Follows a log file as the server appends to it, like `tail -F`. If the file
+// shrinks we assume it was truncated and start over from the top, and if a
+// different file shows up at the same path (the server restarted and made a
+// fresh SquadGame.log) we finish the old one and switch to the new one.
+// Either way next_line says so before handing out lines from the new file.
+pub struct Follower {
+    path: PathBuf,
+    reader: BufReader<File>,
+    meta: Metadata,
+    position: u64,
+    line: u64,
+    // The first PREFIX_LEN bytes of the file, or as many as we've consumed,
+    // so the file can still be checkpointed once it's gone.
+    prefix: Vec<u8>,
+    partial: Vec<u8>,
+    poll_interval: Duration,
+}
+
+// The file we were following before it was rotated or truncated: its first
+// bytes, and how far into it we got.
+pub struct OldFile {
+    pub prefix: Vec<u8>,
+    pub offset: u64,
+    pub line: u64,
+}
+
+pub enum Followed {
+    Line(LogLine),
+    // Lines from here on come from a new file, or the top of a truncated one.
+    Rotated(OldFile),
+}
+
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    match (a.created(), b.created()) {
+        (Ok(x), Ok(y)) => x == y,
+        _ => true,
+    }
+}
+
+impl Follower {
+    pub fn open(path: &Path) -> io::Result<Follower> {
+        Follower::open_at(path, 0, 0)
+    }
+
+    // Start following from offset, which should sit on a line boundary and be
+    // line lines into the file.
+    pub fn open_at(path: &Path, offset: u64, line: u64) -> io::Result<Follower> {
+        let mut file = File::open(path)?;
+        let meta = file.metadata()?;
+        let mut prefix = Vec::new();
+        (&mut file)
+            .take(PREFIX_LEN.min(offset))
+            .read_to_end(&mut prefix)?;
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(Follower {
+            path: path.to_path_buf(),
+            reader: BufReader::new(file),
+            meta,
+            position: offset,
+            line,
+            prefix,
+            partial: Vec::new(),
+            poll_interval: Duration::from_millis(500),
+        })
+    }
+
+    // How many bytes of the current file we have consumed. This always sits on
+    // a line boundary between calls to next_line.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    // How many lines of the current file we have consumed.
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    // Check whether the file at our path is still the one we have open and
+    // hasn't been cut short. If we switched or rewound, says where we got to
+    // in the old file.
+    fn check_rotation(&mut self) -> io::Result<Option<OldFile>> {
+        let on_disk = match fs::metadata(&self.path) {
+            Ok(m) => m,
+            // The old file was moved away and the new one isn't there yet.
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        if !same_file(&self.meta, &on_disk) {
+            let file = File::open(&self.path)?;
+            self.meta = file.metadata()?;
+            self.reader = BufReader::new(file);
+        } else if on_disk.len() < self.position {
+            self.reader.seek(SeekFrom::Start(0))?;
+            self.meta = on_disk;
+        } else {
+            return Ok(None);
+        }
+
+        // A line the old file never finished is dropped.
+        let old = OldFile {
+            prefix: self.prefix.split_off(0),
+            offset: self.position - self.partial.len() as u64,
+            line: self.line,
+        };
+        self.position = 0;
+        self.line = 0;
+        self.partial.clear();
+        Ok(Some(old))
+    }
+
+    // Block until a complete line is available and return it, without the
+    // line ending, or until the file is rotated. Invalid UTF-8 is replaced and
+    // flagged as in LogReader.
+    pub fn next_line(&mut self) -> io::Result<Followed> {
+        loop {
+            let n = self.reader.read_until(b'\n', &mut self.partial)?;
+            self.position += n as u64;
+
+            if self.partial.last() == Some(&b'\n') {
+                let wanted = PREFIX_LEN as usize - self.prefix.len().min(PREFIX_LEN as usize);
+                let take = wanted.min(self.partial.len());
+                self.prefix.extend_from_slice(&self.partial[..take]);
+
+                let (text, lossy) = decode_line(&self.partial);
+                let len = self.partial.len() as u64;
+                self.partial.clear();
+                self.line += 1;
+                return Ok(Followed::Line(LogLine {
+                    text,
+                    len,
+                    complete: true,
+                    lossy,
+                }));
+            }
+
+            // We are at the end of what has been written so far. A rotated
+            // file only gets swapped out once we have read all of the old one.
+            match self.check_rotation()? {
+                Some(old) => return Ok(Followed::Rotated(old)),
+                None => thread::sleep(self.poll_interval),
+            }
+        }
+    }
+}
+
+impl Iterator for Follower {
+    type Item = io::Result<Followed>;
+
+    fn next(&mut self) -> Option<io::Result<Followed>> {
+        Some(self.next_line())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    fn line(follower: &mut Follower) -> LogLine {
+        match follower.next_line().unwrap() {
+            Followed::Line(line) => line,
+            Followed::Rotated(_) => panic!("expected a line"),
+        }
+    }
+
+    fn rotated(follower: &mut Follower) -> OldFile {
+        match follower.next_line().unwrap() {
+            Followed::Rotated(old) => old,
+            Followed::Line(line) => panic!("expected a rotation, got {:?}", line.text),
+        }
+    }
+
+    #[test]
+    fn flags_lines_that_arent_utf8() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("SquadGame.log");
+        fs::write(&path, b"Player:B\xf6b\r\nfine\n").unwrap();
+
+        let mut follower = Follower::open(&path).unwrap();
+        let first = line(&mut follower);
+        assert_eq!(first.text, "Player:B\u{fffd}b");
+        assert!(first.lossy);
+        assert_eq!(first.len, 12);
+        let second = line(&mut follower);
+        assert_eq!(second.text, "fine");
+        assert!(!second.lossy);
+        assert_eq!(follower.position(), 17);
+        assert_eq!(follower.line(), 2);
+    }
+
+    #[test]
+    fn switches_to_a_new_file_at_the_same_path() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("SquadGame.log");
+        fs::write(&path, "one\ntwo\n").unwrap();
+
+        let mut follower = Follower::open(&path).unwrap();
+        line(&mut follower);
+        line(&mut follower);
+        // Half a line that never gets finished.
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"thr")
+            .unwrap();
+        fs::rename(&path, dir.path().join("SquadGame-backup.log")).unwrap();
+        fs::write(&path, "new\n").unwrap();
+
+        let old = rotated(&mut follower);
+        assert_eq!(old.prefix, b"one\ntwo\n");
+        assert_eq!(old.offset, 8);
+        assert_eq!(old.line, 2);
+        assert_eq!(follower.position(), 0);
+        assert_eq!(follower.line(), 0);
+        assert_eq!(line(&mut follower).text, "new");
+        assert_eq!(follower.position(), 4);
+        assert_eq!(follower.line(), 1);
+    }
+
+    #[test]
+    fn starts_over_when_the_file_is_truncated() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("SquadGame.log");
+        fs::write(&path, "first line\n").unwrap();
+
+        let mut follower = Follower::open_at(&path, 0, 0).unwrap();
+        line(&mut follower);
+        OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"again\n")
+            .unwrap();
+
+        let old = rotated(&mut follower);
+        assert_eq!(old.prefix, b"first line\n");
+        assert_eq!(old.offset, 11);
+        assert_eq!(old.line, 1);
+        assert_eq!(line(&mut follower).text, "again");
+        assert_eq!(follower.line(), 1);
+    }
+}
@@ -0,0 +1,229 @@
+use bimap::BiHashMap;
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// The online IDs the server prints next to a player, e.g.
+// "(Online IDs: EOS: 0002a101... steam: 7656119...)".
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct OnlineIds {
+    pub eos: Option<String>,
+    pub steam: Option<String>,
+}
+
+impl OnlineIds {
+    pub fn is_empty(&self) -> bool {
+        self.eos.is_none() && self.steam.is_none()
+    }
+
+    // Every ID we have, written the way the registry keys them.
+    fn keys(&self) -> Vec<String> {
+        let mut keys = Vec::new();
+        if let Some(eos) = &self.eos {
+            keys.push(format!("eos:{}", eos));
+        }
+        if let Some(steam) = &self.steam {
+            keys.push(format!("steam:{}", steam));
+        }
+        keys
+    }
+}
+
+// Parse the inside of an "Online IDs:" block, like "EOS: abc steam: 123".
+pub fn parse_online_ids(s: &str) -> OnlineIds {
+    let mut ids = OnlineIds::default();
+    let words: Vec<&str> = s.split_whitespace().collect();
+    for pair in words.windows(2) {
+        match pair[0] {
+            "EOS:" => ids.eos = Some(String::from(pair[1])),
+            "steam:" => ids.steam = Some(String::from(pair[1])),
+            _ => (),
+        }
+    }
+    ids
+}
+
+// Split a name as printed in the log into the name itself and any online IDs
+// printed after it, e.g. "Bob (Online IDs: EOS: abc steam: 123 | Player
+// Controller ID: BP_PlayerController_C_2147)".
+pub fn split_online_ids(s: &str) -> (String, OnlineIds) {
+    match s.find(" (Online IDs:") {
+        Some(idx) => {
+            let rest = &s[idx + " (Online IDs:".len()..];
+            let end = rest.find([')', '|']).unwrap_or(rest.len());
+            (String::from(&s[..idx]), parse_online_ids(&rest[..end]))
+        }
+        None => (String::from(s), OnlineIds::default()),
+    }
+}
+
+// A name an identity has been seen under, and when.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Alias {
+    pub name: String,
+    pub first_seen: DateTime<FixedOffset>,
+    pub last_seen: DateTime<FixedOffset>,
+}
+
+// Note that name was in use at timestamp.
+fn record_alias(aliases: &mut Vec<Alias>, name: &str, timestamp: &DateTime<FixedOffset>) {
+    match aliases.iter_mut().find(|a| a.name == name) {
+        Some(a) => {
+            if *timestamp < a.first_seen {
+                a.first_seen = *timestamp;
+            }
+            if *timestamp > a.last_seen {
+                a.last_seen = *timestamp;
+            }
+        }
+        None => aliases.push(Alias {
+            name: String::from(name),
+            first_seen: *timestamp,
+            last_seen: *timestamp,
+        }),
+    }
+}
+
+// Add every alias in more to aliases, widening the first and last seen times
+// of names that are in both.
+pub fn merge_aliases(aliases: &mut Vec<Alias>, more: &[Alias]) {
+    for a in more {
+        record_alias(aliases, &a.name, &a.first_seen);
+        record_alias(aliases, &a.name, &a.last_seen);
+    }
+}
+
+// Who is who, across every game we have seen. An identity is named after the
+// first online ID we saw for it ("eos:..." or "steam:..."), and every ID seen
+// alongside it since points at it.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct IdentityRegistry {
+    pub ids: HashMap<String, String>,
+    // The name each identity was last seen under. A name belongs to at most
+    // one identity at a time, so if someone takes over a name the previous
+    // owner loses it.
+    pub names: BiHashMap<String, String>,
+    // Every name each identity has been seen under, in the order we first
+    // saw them.
+    #[serde(default)]
+    pub aliases: HashMap<String, Vec<Alias>>,
+}
+
+impl IdentityRegistry {
+    // Record that name was seen with ids at timestamp. Returns the identity, and if the
+    // ids tied together two identities we had thought were different people,
+    // the one that was merged away.
+    pub fn identify(
+        &mut self,
+        timestamp: &DateTime<FixedOffset>,
+        name: &str,
+        ids: &OnlineIds,
+    ) -> Option<(String, Option<String>)> {
+        let keys = ids.keys();
+        if keys.is_empty() {
+            return None;
+        }
+
+        let mut identity = None;
+        let mut merged = None;
+        for key in &keys {
+            if let Some(existing) = self.ids.get(key) {
+                match &identity {
+                    None => identity = Some(existing.clone()),
+                    Some(i) => {
+                        if i != existing {
+                            merged = Some(existing.clone());
+                        }
+                    }
+                }
+            }
+        }
+        let identity = identity.unwrap_or_else(|| keys[0].clone());
+
+        if let Some(old) = &merged {
+            for v in self.ids.values_mut() {
+                if v == old {
+                    *v = identity.clone();
+                }
+            }
+            self.names.remove_by_right(old);
+            if let Some(old_aliases) = self.aliases.remove(old) {
+                merge_aliases(
+                    self.aliases.entry(identity.clone()).or_default(),
+                    &old_aliases,
+                );
+            }
+        }
+        for key in keys {
+            self.ids.insert(key, identity.clone());
+        }
+        self.names.insert(String::from(name), identity.clone());
+        record_alias(
+            self.aliases.entry(identity.clone()).or_default(),
+            name,
+            timestamp,
+        );
+
+        Some((identity, merged))
+    }
+
+    // The identity currently using name, if any.
+    pub fn identity_of_name(&self, name: &str) -> Option<&String> {
+        self.names.get_by_left(&String::from(name))
+    }
+
+    // The name identity was last seen under.
+    pub fn name_of(&self, identity: &str) -> Option<&String> {
+        self.names.get_by_right(&String::from(identity))
+    }
+
+    // Note that name was in use at timestamp, if we know whose name it is.
+    pub fn seen(&mut self, timestamp: &DateTime<FixedOffset>, name: &str) {
+        if let Some(identity) = self.names.get_by_left(&String::from(name)) {
+            record_alias(
+                self.aliases.entry(identity.clone()).or_default(),
+                name,
+                timestamp,
+            );
+        }
+    }
+
+    // Every name identity has been seen under.
+    pub fn aliases_of(&self, identity: &str) -> &[Alias] {
+        match self.aliases.get(identity) {
+            Some(aliases) => aliases,
+            None => &[],
+        }
+    }
+
+    // Take in everything other knows. An identity of other's that shares an
+    // online ID with one of ours is the same person, and becomes ours. Where
+    // the two disagree about a name, ours wins. Returns other's identities
+    // that were renamed, and what to.
+    pub fn absorb(&mut self, other: &IdentityRegistry) -> HashMap<String, String> {
+        let mut renames = HashMap::new();
+        for (key, theirs) in &other.ids {
+            if let Some(ours) = self.ids.get(key) {
+                if ours != theirs {
+                    renames
+                        .entry(theirs.clone())
+                        .or_insert_with(|| ours.clone());
+                }
+            }
+        }
+        let rename = |identity: &String| renames.get(identity).unwrap_or(identity).clone();
+
+        for (key, theirs) in &other.ids {
+            if !self.ids.contains_key(key) {
+                self.ids.insert(key.clone(), rename(theirs));
+            }
+        }
+        for (name, theirs) in other.names.iter() {
+            let _ = self.names.insert_no_overwrite(name.clone(), rename(theirs));
+        }
+        for (theirs, aliases) in &other.aliases {
+            merge_aliases(self.aliases.entry(rename(theirs)).or_default(), aliases);
+        }
+        renames
+    }
+}
@@ -0,0 +1,97 @@
+use chrono::{DateTime, FixedOffset};
+use glob::glob;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use parse::Parser;
+use reader::LogReader;
+use timezones::Timezones;
+
+// How far into a file we look for its first timestamped line before giving up.
+pub const HEADER_LINES: usize = 1000;
+
+fn is_log_file(path: &Path) -> bool {
+    path.is_file()
+        && match path.extension() {
+            Some(ext) => ext == "log" || ext == "gz" || ext == "zst",
+            None => false,
+        }
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+// Turn what was given on the command line into a list of log files. Each
+// argument can be a file, a directory (every .log, .gz or .zst file directly
+// inside it) or a glob pattern. A file named more than once is only returned
+// once.
+pub fn expand_inputs(args: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+
+    for arg in args {
+        let path = Path::new(arg);
+        if path.is_file() {
+            found.push(path.to_path_buf());
+        } else if path.is_dir() {
+            let mut entries = Vec::new();
+            for entry in fs::read_dir(path)? {
+                let p = entry?.path();
+                if is_log_file(&p) {
+                    entries.push(p);
+                }
+            }
+            entries.sort();
+            found.extend(entries);
+        } else {
+            let paths = glob(arg).map_err(|e| invalid_input(format!("{}: {}", arg, e)))?;
+            let mut matched = false;
+            for p in paths {
+                let p = p.map_err(io::Error::from)?;
+                if p.is_file() {
+                    found.push(p);
+                    matched = true;
+                }
+            }
+            if !matched {
+                return Err(invalid_input(format!("{}: no such file", arg)));
+            }
+        }
+    }
+
+    let mut seen = HashSet::new();
+    found.retain(|p| seen.insert(fs::canonicalize(p).unwrap_or_else(|_e| p.clone())));
+    Ok(found)
+}
+
+// The timestamp of the first line in the file that has one.
+pub fn first_timestamp(path: &Path, parser: &Parser) -> io::Result<Option<DateTime<FixedOffset>>> {
+    let mut reader = LogReader::open(path)?;
+    for _i in 0..HEADER_LINES {
+        match reader.read_log_line()? {
+            Some(line) => {
+                if let Ok(Some(parsed)) = parser.parse_line(&line.text) {
+                    return Ok(Some(parsed.timestamp));
+                }
+            }
+            None => break,
+        }
+    }
+    Ok(None)
+}
+
+// Sort files so they can be streamed through one GameState in the order the
+// server wrote them, reading each in its own zone. Files we can't find a
+// timestamp in go last, as do files we can't read, which are left for
+// whoever reads them in full to report.
+pub fn order_inputs(paths: Vec<PathBuf>, parser: &Parser, zones: &Timezones) -> Vec<PathBuf> {
+    let mut keyed = Vec::new();
+    for p in paths {
+        let t = first_timestamp(&p, &parser.in_zone(zones.source_zone(&p))).unwrap_or(None);
+        keyed.push((t.is_none(), t, p));
+    }
+    keyed.sort();
+    keyed.into_iter().map(|(_n, _t, p)| p).collect()
+}
@@ -0,0 +1,75 @@
+extern crate bimap;
+extern crate chrono;
+extern crate chrono_tz;
+extern crate csv;
+extern crate flate2;
+extern crate fs2;
+extern crate glob;
+extern crate minijinja;
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate tempfile;
+extern crate toml;
+extern crate zstd;
+
+pub mod checkpoint;
+pub mod coverage;
+pub mod error;
+pub mod event;
+pub mod eventlog;
+pub mod export;
+pub mod follow;
+pub mod identity;
+pub mod inputs;
+pub mod merge;
+pub mod overrides;
+pub mod parse;
+pub mod patterns;
+pub mod reader;
+pub mod report;
+pub mod schema;
+pub mod site;
+pub mod state;
+pub mod statefile;
+pub mod stats;
+pub mod timezones;
+pub mod weapons;
+
+pub use checkpoint::{
+    record_moved, record_progress, resume_context, resume_line, resume_offset, FileCheckpoint,
+};
+pub use coverage::{CategoryCoverage, Coverage};
+pub use error::{LineError, LogError};
+pub use event::SquadEvent;
+pub use eventlog::{read_events, rebuild, tail_hash, EventLog, EventRecord};
+pub use export::{
+    export_tables, write_export, Export, ExportFormat, GameRow, KillRow, PlayerRow, WeaponRow,
+};
+pub use follow::{Followed, Follower, OldFile};
+pub use identity::{Alias, IdentityRegistry, OnlineIds};
+pub use inputs::{expand_inputs, order_inputs};
+pub use merge::{merge_state, MergeSummary};
+pub use overrides::{IdentityOverrides, Merge, Split};
+pub use parse::{ParsedLine, Parser};
+pub use patterns::{
+    LineDef, PatternDef, PatternFile, PatternKind, PatternSetDef, Patterns, PATTERN_FILE_VERSION,
+};
+pub use reader::{detect_compression, Compression, LogLine, LogReader};
+pub use report::{match_report, write_match_report, MatchReport, ScoreboardRow};
+pub use schema::{load_state, migrate, state_version, Migration, MIGRATIONS, STATE_VERSION};
+pub use site::{site_templates, write_site};
+pub use state::{
+    apply_event, apply_line, current_game, display_time, effective_identity, prune_games,
+    FileContext, Game, GameState, Player, PlayerState, Revive, WeaponStats,
+};
+pub use statefile::{
+    backup_path, event_log_path, list_backups, lock_statefile, restore_backup, save_state,
+    write_atomic, StateLock, DEFAULT_BACKUPS,
+};
+pub use stats::{
+    category_leaderboard, lifetime_stats, player_key, player_labels, print_lifetime_stats,
+    weapon_leaderboard, CategoryOutput, PlayerOutput, WeaponOutput,
+};
+pub use timezones::{in_zone, FileZone, Timezones};
+pub use weapons::{WeaponCatalog, WeaponCategory, WeaponInfo};
@@ -1,737 +1,937 @@
-extern crate bimap;
 extern crate chrono;
+extern crate chrono_tz;
+extern crate clap;
 extern crate indicatif;
-extern crate regex;
-extern crate serde;
 extern crate serde_json;
+extern crate squadlog;
 
-use chrono::*;
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use clap::{Args, Parser, Subcommand};
 use indicatif::{ProgressBar, ProgressStyle};
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::collections::HashSet;
-use std::env;
+use std::fmt;
 use std::fs;
-use std::hash::{Hash, Hasher};
-use std::str::FromStr;
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-enum PlayerState {
-    Playing,
-    Inactive,
-}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-struct Player {
-    name: String,
-    state: PlayerState,
-    hitpoints: f32,
-    last_damaged: Option<String>,
-    last_spawn_time: Option<DateTime<FixedOffset>>,
-    last_down_time: Option<DateTime<FixedOffset>>,
-    players_killed_by: HashMap<String, u32>,
-    players_killed: HashMap<String, u32>,
-    classes_played: HashSet<String>,
-    players_revived_by: HashMap<String, u32>,
-    players_revived: HashMap<String, u32>,
-}
-impl PartialEq for Player {
-    fn eq(&self, other: &Self) -> bool {
-        self.name == other.name
-    }
-}
-impl Eq for Player {}
-impl Hash for Player {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.name.hash(state);
-    }
-}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-struct Game {
-    map: String,
-    players: HashMap<String, Player>,
-    start_time: DateTime<FixedOffset>,
-}
-
-#[derive(Debug, Clone, Deserialize, Serialize)]
-struct GameState {
-    games: Vec<Game>, // Sorted by start_time, from earliest to latest.
-    current_game_start_time: DateTime<FixedOffset>,
-    last_timestamp: DateTime<FixedOffset>,
-    player_names: Vec<(String, Option<String>)>,
-}
-
-fn seen_player_name(
-    name: &String,
-    names: &Vec<(String, Option<String>)>,
-) -> Vec<(String, Option<String>)> {
-    let mut res = None;
-    for (left, right) in names {
-        if left == name {
-            match right {
-                Some(_t) => res = Some(names.clone()),
-                None => (),
-            }
-        }
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use squadlog::{
+    apply_line, backup_path, current_game, detect_compression, display_time, event_log_path,
+    expand_inputs, export_tables, lifetime_stats, list_backups, load_state, lock_statefile,
+    match_report, merge_state, migrate, order_inputs, print_lifetime_stats, prune_games, rebuild,
+    record_moved, record_progress, restore_backup, resume_context, resume_line, resume_offset,
+    site_templates, state_version, tail_hash, write_export, write_match_report, write_site,
+    Compression, Coverage, EventLog, ExportFormat, Followed, Follower, GameState,
+    IdentityOverrides, LineError, LogError, LogReader, Patterns, SquadEvent, Timezones,
+    WeaponCatalog, DEFAULT_BACKUPS, STATE_VERSION,
+};
+
+// How many skipped lines to print before just counting them.
+const SHOW_SKIPPED: usize = 20;
+// How many unmatched line shapes the coverage report lists.
+const SHOW_SHAPES: usize = 25;
+
+// Where the state lives, and how many old copies to keep when saving it.
+struct Statefile {
+    path: PathBuf,
+    backups: usize,
+}
+
+// Say what we couldn't do to path and why, and give up.
+fn fail<E: fmt::Display>(what: &str, path: &Path, e: E) -> ! {
+    eprintln!("couldn't {} {}: {}", what, path.display(), e);
+    process::exit(1);
+}
+
+// The statefile's contents, for commands that can't do anything without it.
+fn read_statefile(statefile: &Statefile) -> String {
+    fs::read_to_string(&statefile.path).unwrap_or_else(|e| fail("read", &statefile.path, e))
+}
+
+// The logs named by args, or exit saying which of them isn't there.
+fn log_files(args: &[String]) -> Vec<PathBuf> {
+    expand_inputs(args).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
+}
+
+fn save_state(statefile: &Statefile, g: &GameState) {
+    squadlog::save_state(&statefile.path, g, statefile.backups).expect("Error writing statefile");
+}
+
+// Get the events behind g onto disk, then save g, which commits them.
+fn save_with_events(statefile: &Statefile, g: &mut GameState, events: &mut EventLog) {
+    g.events_len = events.commit().expect("Error writing event log");
+    g.events_tail =
+        tail_hash(&event_log_path(&statefile.path), g.events_len).expect("Error reading event log");
+    save_state(statefile, g);
+}
+
+// Parse and apply one line, recording its events. Returns true if the line
+// ended a match. A line that fails is recorded too, so a rebuild under fixed
+// rules can apply it, unless it stops a strict run, which reads it again next
+// time.
+fn process_line(
+    line: &str,
+    parser: &squadlog::Parser,
+    g: &mut GameState,
+    events: &mut EventLog,
+    strict: bool,
+) -> Result<bool, LogError> {
+    let parsed = match parser.parse_line(line)? {
+        Some(parsed) => parsed,
+        None => return Ok(false),
+    };
+    let applied = apply_line(&parsed, g);
+    if applied.is_ok() || !strict {
+        events
+            .record_line(parsed.timestamp, &parsed.events)
+            .expect("Error writing event log");
     }
+    applied?;
+    Ok(parsed.events.iter().any(|e| match e {
+        SquadEvent::MatchStateChanged { to, .. } => to == "WaitingPostMatch",
+        _ => false,
+    }))
+}
 
-    match res {
-        Some(t) => t,
-        None => {
-            let mut my_names = names.clone();
-            my_names.push((String::from(name), None));
-            my_names
+// Why a log stopped being read: a line failed in strict mode, or the file
+// itself couldn't be read, like an archive that was cut short.
+enum FileError {
+    Line(LineError),
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileError::Line(e) => write!(f, "{}", e),
+            FileError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
         }
     }
 }
 
-fn lookup_player_name(
-    name: &String,
-    names: &Vec<(String, Option<String>)>,
-) -> (String, Vec<(String, Option<String>)>) {
-    let mut res = None;
-    for (left, right) in names {
-        match right {
-            Some(realname) => {
-                if name == realname {
-                    res = Some(left);
+// Lines that can't be parsed or applied are skipped and remembered, as are
+// logs that can't be read to the end, unless we are being strict, in which
+// case the first one stops the run.
+struct BadLines {
+    strict: bool,
+    skipped: Vec<LineError>,
+    unreadable: Vec<FileError>,
+}
+
+impl BadLines {
+    fn check(
+        &mut self,
+        result: Result<bool, LogError>,
+        path: &Path,
+        line: u64,
+    ) -> Result<bool, LineError> {
+        match result {
+            Ok(ended) => Ok(ended),
+            Err(error) => {
+                let e = LineError {
+                    path: path.to_path_buf(),
+                    line,
+                    error,
+                };
+                if self.strict {
+                    return Err(e);
                 }
+                self.skipped.push(e);
+                Ok(false)
             }
-            None => (),
         }
     }
 
-    match res {
-        Some(n) => (n.clone(), names.clone()),
-        None => {
-            let mut new_names: Vec<(String, Option<String>)> = Vec::new();
-            let my_names = names.clone();
-            let mut found_name = None;
-            for (left, right) in my_names {
-                // Compute the length of the names in counts of characters rather
-                // than byte lengths of the strings.
-                let t1: Vec<char> = name.chars().collect();
-                let t2: Vec<char> = left.chars().collect();
-                let name_len = t1.len();
-                let left_len = t2.len();
-
-                if left_len <= name_len {
-                    let tag_len = name_len - left_len;
-                    let s1: String = name.chars().take(name_len).skip(tag_len).collect();
-
-                    if s1 == left {
-                        new_names.push((left.clone(), Some(name.clone())));
-                        found_name = Some(left.clone())
-                    } else {
-                        new_names.push((left, right));
-                    }
-                } else {
-                    new_names.push((left, right));
-                }
-            }
-
-            (found_name.unwrap(), new_names)
+    // A log that couldn't be read. Whatever was applied from it still counts.
+    fn unreadable(&mut self, path: &Path, error: io::Error) -> Result<(), FileError> {
+        let e = FileError::Io(path.to_path_buf(), error);
+        if self.strict {
+            return Err(e);
         }
+        self.unreadable.push(e);
+        Ok(())
     }
-}
 
-fn game_ended(_timestamp: &DateTime<FixedOffset>, _g: &Game) {
-    //println!("ending at {}, Game: {:?}", timestamp, g);
-}
-
-fn get_dt(s: &str) -> Option<DateTime<FixedOffset>> {
-    let s1 = format!("{} {}", s, "+0000").to_owned();
-    let q = DateTime::parse_from_str(&s1[..], "%Y.%m.%d-%H.%M.%S:%3f %z");
-    match q {
-        Ok(v) => Some(v),
-        Err(e) => {
-            println!("{}", e);
-            None
+    fn report(&self) {
+        if !self.unreadable.is_empty() {
+            eprintln!(
+                "{} logs couldn't be read to the end:",
+                self.unreadable.len()
+            );
+            for e in &self.unreadable {
+                eprintln!("  {}", e);
+            }
+        }
+        if self.skipped.is_empty() {
+            return;
+        }
+        eprintln!("{} lines were skipped:", self.skipped.len());
+        for e in self.skipped.iter().take(SHOW_SKIPPED) {
+            eprintln!("  {}", e);
+        }
+        if self.skipped.len() > SHOW_SKIPPED {
+            eprintln!("  and {} more", self.skipped.len() - SHOW_SKIPPED);
         }
     }
 }
 
-// Game state helper routines.
+// What one run over the logs works with besides the state.
+struct Run<'a> {
+    patterns: &'a Patterns,
+    bad: BadLines,
+    events: EventLog,
+    // Where to write a report for each match that ends, if anywhere.
+    reports: Option<PathBuf>,
+}
 
-// The current game is the one that started at the time indicated by current_game_start_time.
-fn get_current_game_idx(g: &GameState) -> usize {
-    g.games
-        .binary_search_by_key(&g.current_game_start_time, |t| t.start_time)
-        .expect("Could not find game")
+// Write the report for the match that just ended. A report we can't write
+// doesn't stop the run, since the match is in the state either way. A match
+// we read a second time already had its report written.
+fn report_match(run: &Run, g: &GameState) {
+    let dir = match &run.reports {
+        Some(dir) if !g.replaying => dir,
+        _ => return,
+    };
+    let game = match current_game(g) {
+        Some(game) => game,
+        None => return,
+    };
+    if let Err(e) = write_match_report(dir, &match_report(g, game)) {
+        eprintln!("couldn't write a match report to {}: {}", dir.display(), e);
+    }
 }
 
-// Game state updating routines.
+// Run over the rest of one log, advancing pb by the bytes read from disk as
+// we go. Only complete lines are consumed from plain logs, so a line the
+// server is halfway through writing is picked up whole on the next run.
+// Compressed logs are archives that won't grow, so their last line counts
+// even without a newline. Returns how many lines weren't valid UTF-8. If a
+// line fails in strict mode, or the file can't be read any further, progress
+// is recorded up to the line before.
+fn ingest_file(
+    path: &Path,
+    offset: u64,
+    g: &mut GameState,
+    run: &mut Run,
+    pb: &ProgressBar,
+    base: u64,
+) -> Result<u64, FileError> {
+    let io_error = |e| FileError::Io(path.to_path_buf(), e);
+    let parser = run
+        .patterns
+        .parser_for_file(path)
+        .map_err(io_error)?
+        .in_zone(g.timezones.source_zone(path));
+    g.generation = Some(String::from(parser.generation()));
+    g.source_timezone = Some(parser.zone());
+    resume_context(g, path).map_err(io_error)?;
+    run.events.start_file(path, g);
+    let mut reader = LogReader::open_at(path, offset).map_err(io_error)?;
+    let complete_only = reader.compression() == Compression::Plain;
 
-// Update that one player revived another.
-fn player_revived(
-    _timestamp: &DateTime<FixedOffset>,
-    reviving: &str,
-    revived: &str,
-    g: &GameState,
-) -> GameState {
-    let mut my_games = g.games.clone();
-    let game_idx = get_current_game_idx(&g);
-    let current_game = my_games.get_mut(game_idx).expect("Invalid index for game");
+    let mut new: u64 = 0;
+    let mut line_no = resume_line(g, path).map_err(io_error)?;
+    let mut lossy_lines: u64 = 0;
+    let mut stopped = None;
+    loop {
+        let line = match reader.read_log_line() {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                stopped = Some(io_error(e));
+                break;
+            }
+        };
+        if complete_only && !line.complete {
+            break;
+        }
+        let result = process_line(&line.text, &parser, g, &mut run.events, run.bad.strict);
+        match run.bad.check(result, path, line_no + 1) {
+            Ok(true) => report_match(run, g),
+            Ok(false) => (),
+            Err(e) => {
+                stopped = Some(FileError::Line(e));
+                break;
+            }
+        }
+        new += line.len;
+        line_no += 1;
+        if line.lossy {
+            lossy_lines += 1;
+        }
+        pb.set_position(base + reader.raw_bytes_read());
+    }
 
-    // Find both players.
-    let reviver_found = current_game.players.get(&String::from(reviving));
-    let revivee_found = current_game.players.get(&String::from(revived));
+    record_progress(g, path, offset + new, line_no).map_err(io_error)?;
+    match stopped {
+        Some(e) => Err(e),
+        None => Ok(lossy_lines),
+    }
+}
 
-    let f = match (reviver_found, revivee_found) {
-        (Some(reviver), Some(revivee)) => {
-            let mut players_revived = reviver.players_revived.clone();
-            let mut players_revived_by = revivee.players_revived_by.clone();
-            *players_revived.entry(String::from(revived)).or_insert(0) += 1;
-            *players_revived_by.entry(String::from(revived)).or_insert(0) += 1;
+// Where to start reading the log at path, and how many of its bytes on disk
+// that leaves. Compressed logs have to be read from the top to get to the
+// offset, so all of their bytes count.
+fn remaining(g: &GameState, path: &Path) -> io::Result<(u64, u64)> {
+    let offset = resume_offset(g, path)?;
+    let len = fs::metadata(path)?.len();
+    let plain = detect_compression(path)? == Compression::Plain;
+    Ok((offset, if plain { len - offset } else { len }))
+}
 
-            let new_reviver = Player {
-                players_revived: players_revived,
-                ..reviver.clone()
-            };
-            let new_revivee = Player {
-                players_revived_by: players_revived_by,
-                hitpoints: 5.0,
-                ..revivee.clone()
-            };
-            Some((new_reviver, new_revivee))
+// Run over every log once, oldest first, with one progress bar for the lot.
+// A log that can't be read is reported and the rest are still read.
+fn ingest(logfiles: &[PathBuf], g: &mut GameState, run: &mut Run) -> Result<(), FileError> {
+    let mut todo = Vec::new();
+    for path in logfiles {
+        match remaining(g, path) {
+            Ok((offset, size)) => todo.push((path, offset, size)),
+            Err(e) => run.bad.unreadable(path, e)?,
         }
-        _ => None,
-    };
+    }
+    let total: u64 = todo.iter().map(|(_path, _offset, size)| size).sum();
 
-    let new_games = match f {
-        Some((x, y)) => {
-            let mut t1 = current_game.players.clone();
-            *t1.get_mut(&x.name).unwrap() = x.clone();
-            let mut t2 = t1.clone();
-            *t2.get_mut(&y.name).unwrap() = y.clone();
-            my_games.clone()
-        }
-        None => my_games.clone(),
-    };
+    let pb = ProgressBar::new(total);
+    pb.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+        .progress_chars("#>-"));
 
-    GameState {
-        games: new_games,
-        current_game_start_time: g.current_game_start_time,
-        last_timestamp: g.last_timestamp,
-        player_names: g.player_names.clone(),
-    }
-}
-
-// Add a player to the game state.
-fn player_spawned(
-    timestamp: &DateTime<FixedOffset>,
-    name: &str,
-    class: &str,
-    g: &GameState,
-) -> GameState {
-    let mut my_games = g.games.clone();
-    let game_idx = get_current_game_idx(&g);
-    let current_game = my_games.get_mut(game_idx).expect("Invalid index for game");
-
-    // See if the player is in the current_game player hash set.
-    let mut classes_played = HashSet::new();
-    classes_played.insert(String::from(class));
-
-    let new_player = match current_game.players.get(&String::from(name)) {
-        Some(player) => {
-            // A player existed, update what classes they have played and their last
-            // spawn time
-            Player {
-                state: PlayerState::Playing,
-                classes_played: classes_played
-                    .union(&player.classes_played)
-                    .cloned()
-                    .collect(),
-                hitpoints: 100.0,
-                last_damaged: None,
-                last_spawn_time: Some(timestamp.clone()),
-                ..player.clone()
-            }
+    let mut base: u64 = 0;
+    let mut lossy_lines: u64 = 0;
+    for (path, offset, size) in todo {
+        match ingest_file(path, offset, g, run, &pb, base) {
+            Ok(lossy) => lossy_lines += lossy,
+            Err(FileError::Io(path, e)) => run.bad.unreadable(&path, e)?,
+            Err(e) => return Err(e),
         }
-        None => Player {
-            name: String::from(name),
-            state: PlayerState::Inactive,
-            classes_played: classes_played.clone(),
-            hitpoints: 100.0,
-            last_damaged: None,
-            last_down_time: None,
-            last_spawn_time: Some(timestamp.clone()),
-            players_killed: HashMap::new(),
-            players_killed_by: HashMap::new(),
-            players_revived: HashMap::new(),
-            players_revived_by: HashMap::new(),
-        },
-    };
+        base += size;
+    }
+    pb.finish();
 
-    current_game
-        .players
-        .insert(new_player.clone().name, new_player.clone());
-    GameState {
-        games: my_games,
-        current_game_start_time: g.current_game_start_time,
-        ..g.clone()
+    if lossy_lines > 0 {
+        eprintln!("{} lines were not valid UTF-8", lossy_lines);
     }
+    Ok(())
 }
 
-// Called when a new map is loaded.
-fn starting_game(timestamp: &DateTime<FixedOffset>, map_name: &str, g: &GameState) -> GameState {
-    // Make a new Game.
-    let new_game = Game {
-        map: String::from(map_name),
-        players: HashMap::new(),
-        start_time: timestamp.clone(),
-    };
-    let mut games = g.games.clone();
-    games.push(new_game);
-
-    // Return a new GameState with our new game in it.
-    GameState {
-        games: games,
-        current_game_start_time: timestamp.clone(),
-        ..g.clone()
-    }
-}
-
-fn player_damaged(
-    _timestamp: &DateTime<FixedOffset>,
-    shooter: &str,
-    damage: f32,
-    target: &str,
-    _weapon: &str,
-    g: &GameState,
-) -> GameState {
-    let mut my_games = g.games.clone();
-    let game_idx = get_current_game_idx(&g);
-    let current_game = my_games.get_mut(game_idx).expect("Invalid index for game");
-    let (resolved_name, new_player_names) =
-        lookup_player_name(&String::from(target), &g.player_names);
-
-    let retrieved_player = current_game
-        .players
-        .get(&resolved_name)
-        .expect("Should have a player if they are shot");
-
-    // If we know who did the damage, mark that in the player state for the player
-    // that was shot.
-    let new_shooter = if shooter != "nullptr" {
-        Some(String::from(shooter))
+// What the spinner says while following a log.
+fn follow_status(g: &GameState, lossy_lines: u64) -> String {
+    if lossy_lines > 0 {
+        format!(
+            "{} games, {} lines were not valid UTF-8",
+            g.games.len(),
+            lossy_lines
+        )
     } else {
-        retrieved_player.last_damaged.clone()
-    };
+        format!("{} games", g.games.len())
+    }
+}
 
-    let updated_player = Player {
-        last_damaged: new_shooter,
-        hitpoints: retrieved_player.hitpoints - damage,
-        ..retrieved_player.clone()
-    };
+// Keep the log open and process lines as the server writes them. The state is
+// saved every time a match ends, so a crash only loses the match in progress.
+// Skipped lines are printed as they happen.
+// Pick the parser for the log at path and pick up its game context, when we
+// start following it and again whenever it's rotated.
+fn start_following(path: &Path, g: &mut GameState, run: &mut Run) -> io::Result<squadlog::Parser> {
+    let parser = run
+        .patterns
+        .parser_for_file(path)?
+        .in_zone(g.timezones.source_zone(path));
+    g.generation = Some(String::from(parser.generation()));
+    g.source_timezone = Some(parser.zone());
+    resume_context(g, path)?;
+    run.events.start_file(path, g);
+    Ok(parser)
+}
 
-    *current_game.players.get_mut(&updated_player.name).unwrap() = updated_player.clone();
-    GameState {
-        games: my_games,
-        player_names: new_player_names,
-        ..*g
-    }
-}
-
-fn player_down(timestamp: &DateTime<FixedOffset>, player: &str, g: &GameState) -> GameState {
-    let mut my_games = g.games.clone();
-    let game_idx = get_current_game_idx(&g);
-    let current_game = my_games.get_mut(game_idx).expect("Invalid index for game");
-    let (resolved_player_name, new_player_names) =
-        lookup_player_name(&String::from(player), &g.player_names);
-
-    let retrieved_player = current_game
-        .players
-        .get(&resolved_player_name)
-        .expect("Should have a player if they go down");
-
-    // Who was the player last shot by? Update their stats with that information.
-
-    let mut m_player_names = new_player_names.clone();
-    match &retrieved_player.last_damaged {
-        Some(killer_name) => {
-            let (resolved_killer_name, new_player_names_2) =
-                lookup_player_name(&String::from(killer_name), &new_player_names.clone());
-            m_player_names = new_player_names_2;
-
-            let killing_player = current_game
-                .players
-                .get(&resolved_killer_name)
-                .expect("Should have this");
-            let mut downed_killed_by = retrieved_player.players_killed_by.clone();
-            *downed_killed_by.entry(resolved_killer_name).or_insert(0) += 1;
-            let mut killing_killed = killing_player.players_killed.clone();
-            *killing_killed.entry(resolved_player_name).or_insert(0) += 1;
-
-            let new_downed_player = Player {
-                last_damaged: None,
-                last_down_time: Some(*timestamp),
-                players_killed_by: downed_killed_by,
-                ..retrieved_player.clone()
-            };
-
-            let new_killing_player = Player {
-                players_killed: killing_killed,
-                ..killing_player.clone()
-            };
-
-            *current_game
-                .players
-                .get_mut(&new_downed_player.name)
-                .unwrap() = new_downed_player.clone();
-            *current_game
-                .players
-                .get_mut(&new_killing_player.name)
-                .unwrap() = new_killing_player.clone();
-        }
-        None => (),
-    };
+fn follow(
+    statefile: &Statefile,
+    path: &Path,
+    g: &mut GameState,
+    run: &mut Run,
+) -> Result<(), FileError> {
+    let io_error = |e| FileError::Io(path.to_path_buf(), e);
+    let mut parser = start_following(path, g, run).map_err(io_error)?;
+    let offset = resume_offset(g, path).map_err(io_error)?;
+    let line = resume_line(g, path).map_err(io_error)?;
+    let mut follower = Follower::open_at(path, offset, line).map_err(io_error)?;
+
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} [{elapsed_precise}] {bytes} {msg}"),
+    );
 
-    GameState {
-        games: my_games,
-        player_names: m_player_names,
-        ..*g
+    let mut lossy_lines: u64 = 0;
+    loop {
+        let start = follower.position();
+        let line = match follower.next_line() {
+            Ok(Followed::Line(line)) => line,
+            Ok(Followed::Rotated(old)) => {
+                // A server that's been updated starts a fresh log, so the
+                // pattern set is picked again.
+                record_moved(g, path, &old.prefix, old.offset, old.line);
+                parser = start_following(path, g, run).map_err(io_error)?;
+                save_with_events(statefile, g, &mut run.events);
+                pb.set_position(0);
+                continue;
+            }
+            Err(e) => {
+                record_progress(g, path, start, follower.line()).map_err(io_error)?;
+                return Err(io_error(e));
+            }
+        };
+        if line.lossy {
+            lossy_lines += 1;
+            pb.set_message(&follow_status(g, lossy_lines));
+        }
+        let result = process_line(&line.text, &parser, g, &mut run.events, run.bad.strict);
+        match run.bad.check(result, path, follower.line()) {
+            Ok(true) => {
+                record_progress(g, path, follower.position(), follower.line()).map_err(io_error)?;
+                save_with_events(statefile, g, &mut run.events);
+                report_match(run, g);
+                pb.set_message(&follow_status(g, lossy_lines));
+            }
+            Ok(false) => (),
+            Err(e) => {
+                record_progress(g, path, start, follower.line() - 1).map_err(io_error)?;
+                return Err(FileError::Line(e));
+            }
+        }
+        for e in run.bad.skipped.drain(..) {
+            pb.println(format!("skipped {}", e));
+        }
+        pb.set_position(follower.position());
     }
 }
 
-// Parse routines.
-
-struct Regexes {
-    logsquad_damaged: Regex,
-    logsquad_revived: Regex,
-    line: Regex,
-    trace_role: Regex,
-    trace_down: Regex,
-    trace_statechange: Regex,
-    game_state_change: Regex,
-    world_state_change: Regex,
+// Report how much of the logs the parser understands. Nothing is applied to
+// any state.
+fn coverage(args: &[String], patterns: &Patterns, zones: &Timezones) {
+    let logfiles = log_files(args);
+    let mut cov = Coverage::new();
+    for path in &logfiles {
+        let parser = patterns
+            .parser_for_file(path)
+            .expect("Error opening log file")
+            .in_zone(zones.source_zone(path));
+        let mut reader = LogReader::open(path).expect("Error opening log file");
+        while let Some(line) = reader.read_log_line().expect("Error reading log file") {
+            cov.add_line(&parser, &line.text);
+        }
+    }
+    print!("{}", cov.report(SHOW_SHAPES));
 }
 
-fn parse_logsquad(
-    timestamp: &DateTime<FixedOffset>,
-    msg: &str,
-    g: &GameState,
-    r: &Regexes,
-) -> Option<GameState> {
-    let g1 = match r.logsquad_revived.captures(msg) {
-        Some(x) => Some(player_revived(timestamp, &x[1], &x[2], g)),
-        None => None,
-    };
+// Upgrade a statefile to the current version, or with dry_run just say what
+// that would change.
+fn migrate_statefile(statefile: &Statefile, dry_run: bool) {
+    let contents = read_statefile(statefile);
+    let mut state: serde_json::Value =
+        serde_json::from_str(&contents).unwrap_or_else(|e| fail("read", &statefile.path, e));
+    let version = state_version(&state);
+    let applied = migrate(&mut state).unwrap_or_else(|e| fail("migrate", &statefile.path, e));
+    if applied.is_empty() {
+        println!(
+            "{} is already version {}",
+            statefile.path.display(),
+            version
+        );
+        return;
+    }
+    println!(
+        "{} is version {}, migrating to version {}",
+        statefile.path.display(),
+        version,
+        STATE_VERSION
+    );
+    for (m, notes) in &applied {
+        println!("  from version {}: {}", m.from, m.description);
+        for note in notes {
+            println!("    {}", note);
+        }
+    }
+    // Make sure what we'd write loads before writing it.
+    let g: GameState =
+        serde_json::from_value(state).unwrap_or_else(|e| fail("migrate", &statefile.path, e));
+    if dry_run {
+        println!("dry run, {} was not changed", statefile.path.display());
+    } else {
+        save_state(statefile, &g);
+    }
+}
 
-    let g2 = match r.logsquad_damaged.captures(msg) {
-        Some(x) => {
-            // Sometimes, someone damages nullptr. Ignore that.
-            if &x[1] == "nullptr" {
-                None
-            } else {
-                match g1 {
-                    Some(t) => Some(player_damaged(
-                        timestamp,
-                        &x[3],
-                        f32::from_str(&x[2]).unwrap(),
-                        &x[1],
-                        &x[4],
-                        &t,
-                    )),
-                    None => Some(player_damaged(
-                        timestamp,
-                        &x[3],
-                        f32::from_str(&x[2]).unwrap(),
-                        &x[1],
-                        &x[4],
-                        g,
-                    )),
+// With no backup given, list the backups. Otherwise put that one back.
+fn restore(statefile: &Statefile, n: Option<usize>) {
+    let n = match n {
+        Some(n) => n,
+        None => {
+            for (i, path) in list_backups(&statefile.path, statefile.backups)
+                .iter()
+                .enumerate()
+            {
+                match fs::read_to_string(path).and_then(|contents| load_state(&contents)) {
+                    Ok(g) => println!(
+                        "{}  {}  {} games, last line at {}",
+                        i + 1,
+                        path.display(),
+                        g.games.len(),
+                        g.last_timestamp
+                    ),
+                    Err(e) => println!("{}  {}  unreadable: {}", i + 1, path.display(), e),
                 }
             }
+            return;
         }
-        None => match g1 {
-            Some(x) => Some(x),
-            None => None,
-        },
     };
+    let backup = backup_path(&statefile.path, n);
+    let whole = restore_backup(&statefile.path, n, statefile.backups)
+        .unwrap_or_else(|e| fail("restore", &backup, e));
+    println!(
+        "restored {} from {}",
+        statefile.path.display(),
+        backup.display()
+    );
+    if !whole {
+        println!("the event log has changed since that backup was saved, so it can't be rebuilt");
+    }
+}
 
-    g2
-}
-
-fn parse_logtrace(
-    timestamp: &DateTime<FixedOffset>,
-    msg: &str,
-    g: &GameState,
-    r: &Regexes,
-) -> Option<GameState> {
-    let g1 = match r.trace_role.captures(msg) {
-        Some(c) => {
-            if &c[2] != "nullptr" {
-                Some(player_spawned(timestamp, &c[1], &c[2], g))
-            } else {
-                None
-            }
-        }
-        None => None,
-    };
+// Replace the state with one recomputed from its event log under the current
+// rules. The old state is kept as a backup.
+fn rebuild_statefile(statefile: &Statefile, overrides: IdentityOverrides, zones: Timezones) {
+    let contents = read_statefile(statefile);
+    let mut old = load_state(&contents).unwrap_or_else(|e| fail("read", &statefile.path, e));
+    old.overrides = overrides;
+    old.timezones = zones;
+    let log = event_log_path(&statefile.path);
+    let (g, failed) = rebuild(&old, &log).unwrap_or_else(|e| fail("rebuild from", &log, e));
+    println!(
+        "rebuilt {} games from the event log, where there were {}",
+        g.games.len(),
+        old.games.len()
+    );
+    if failed > 0 {
+        println!("{} lines no longer apply and were skipped", failed);
+    }
+    save_state(statefile, &g);
+}
 
-    let g2 = match r.trace_down.captures(msg) {
-        Some(c) => {
-            if &c[1] == "nullptr" {
-                None
-            } else {
-                match g1 {
-                    Some(t) => Some(player_down(timestamp, &c[1], &t)),
-                    None => Some(player_down(timestamp, &c[1], g)),
-                }
-            }
-        }
-        None => match g1 {
-            Some(t) => Some(t),
-            None => None,
+// Load the statefile. Commands that only read it have nothing to work with
+// if it isn't there, which is most likely a mistyped path.
+fn load_statefile(statefile: &Statefile, config: &Config) -> GameState {
+    let contents = read_statefile(statefile);
+    let mut g = load_state(&contents).unwrap_or_else(|e| fail("read", &statefile.path, e));
+    g.overrides = config.overrides();
+    g.timezones = config.timezones();
+    g
+}
+
+// Load the statefile, or start a fresh state if there isn't one yet.
+fn load_or_create_statefile(statefile: &Statefile, config: &Config) -> GameState {
+    if !statefile.path.exists() {
+        let mut g = GameState::new();
+        g.overrides = config.overrides();
+        g.timezones = config.timezones();
+        return g;
+    }
+    load_statefile(statefile, config)
+}
+
+fn ingest_command(statefile: &Statefile, config: &Config, opts: &IngestOptions) {
+    let patterns = config.patterns();
+    let _lock = lock_statefile(&statefile.path).expect("Error locking statefile");
+    let mut g = load_or_create_statefile(statefile, config);
+
+    let inputs = log_files(&opts.logs);
+    let mut logfiles = order_inputs(inputs, patterns.default_parser(), &g.timezones);
+
+    let mut run = Run {
+        patterns: &patterns,
+        bad: BadLines {
+            strict: opts.strict,
+            skipped: Vec::new(),
+            unreadable: Vec::new(),
         },
+        events: EventLog::open(&event_log_path(&statefile.path), g.events_len)
+            .expect("Error opening event log"),
+        reports: opts.reports.clone(),
     };
-
-    let g3 = match r.trace_statechange.captures(msg) {
-        Some(c) => match g2 {
-            Some(t) => {
-                let newg = GameState {
-                    player_names: seen_player_name(&String::from(&c[1]), &t.player_names),
-                    ..t.clone()
-                };
-                Some(newg)
-            }
+    let result = if opts.follow {
+        // Catch up on everything older, then keep following the newest log.
+        let newest = match logfiles.pop() {
+            Some(newest) => newest,
             None => {
-                let newg = GameState {
-                    player_names: seen_player_name(&String::from(&c[1]), &g.player_names),
-                    ..g.clone()
-                };
-                Some(newg)
+                eprintln!("no log files to follow");
+                process::exit(1);
             }
-        },
-        None => match g2 {
-            Some(t) => Some(t),
-            None => None,
-        },
+        };
+        ingest(&logfiles, &mut g, &mut run).and_then(|()| {
+            run.bad.report();
+            run.bad.skipped.clear();
+            save_with_events(statefile, &mut g, &mut run.events);
+            follow(statefile, &newest, &mut g, &mut run)
+        })
+    } else {
+        ingest(&logfiles, &mut g, &mut run)
     };
 
-    g3
-}
-
-fn parse_game_state(
-    timestamp: &DateTime<FixedOffset>,
-    msg: &str,
-    g: &GameState,
-    r: &Regexes,
-) -> Option<GameState> {
-    match r.game_state_change.captures(msg) {
-        Some(x) => {
-            match &x[2] {
-                "WaitingPostMatch" => {
-                    let mut my_games = g.games.clone();
-                    if my_games.len() > 0 {
-                        let game_idx = get_current_game_idx(&g);
-                        let current_game =
-                            my_games.get_mut(game_idx).expect("Invalid index for game");
-                        game_ended(timestamp, current_game);
-                        Some(GameState {
-                            player_names: Vec::new(),
-                            ..g.clone()
-                        })
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
-            }
-            /*if &x[2] == "WaitingPostMatch" {
-                None
-            } */
-        }
-        None => None,
+    // Whatever was applied before a line or a log failed is still good, so
+    // keep it.
+    if let Err(e) = result {
+        run.bad.report();
+        save_with_events(statefile, &mut g, &mut run.events);
+        eprintln!("{}", e);
+        process::exit(1);
     }
+    run.bad.report();
+    save_with_events(statefile, &mut g, &mut run.events);
 }
 
-fn parse_world_state(
-    timestamp: &DateTime<FixedOffset>,
-    msg: &str,
-    g: &GameState,
-    r: &Regexes,
-) -> Option<GameState> {
-    let g1 = match r.world_state_change.captures(msg) {
-        Some(x) => Some(starting_game(timestamp, &x[1], g)),
-        None => None,
+// The lifetime stats of the player called name, looked up by the name they
+// are listed under, then their identity, then any name they've played as.
+fn player(g: &GameState, catalog: &WeaponCatalog, name: &str) {
+    let mut players = lifetime_stats(g, catalog);
+    let key = match players.get(name) {
+        Some(_p) => Some(String::from(name)),
+        None => players
+            .iter()
+            .find(|(_key, p)| {
+                p.identity.as_deref() == Some(name) || p.aliases.iter().any(|a| a.name == name)
+            })
+            .map(|(key, _p)| key.clone()),
     };
+    match key.and_then(|key| players.remove(&key)) {
+        Some(p) => println!(
+            "{}",
+            serde_json::to_string_pretty(&p).expect("serialization error")
+        ),
+        None => {
+            eprintln!("no player called {}", name);
+            process::exit(1);
+        }
+    }
+}
 
-    g1
-}
-
-fn parse_line(line: &str, g: &GameState, r: &Regexes) -> Option<GameState> {
-    match r.line.captures(line) {
-        Some(c) => {
-            // Update the timestamp if the current line is newer, even if we won't process this
-            // line into an update to the game state.
-            let timestamp = get_dt(&c[1]).unwrap();
-            if timestamp < g.last_timestamp {
-                None
-            } else {
-                // TODO: this throws away timestamps sometimes.
-                let cur_g = GameState {
-                    last_timestamp: timestamp,
-                    ..g.clone()
-                };
-
-                match &c[2] {
-                    "LogSquad" => parse_logsquad(&timestamp, &c[3], &cur_g, &r),
-                    "LogSquadTrace" => parse_logtrace(&timestamp, &c[3], &cur_g, &r),
-                    "LogGameState" => parse_game_state(&timestamp, &c[3], &cur_g, &r),
-                    "LogWorld" => parse_world_state(&timestamp, &c[3], &cur_g, &r),
-                    _ => Some(cur_g),
-                }
+fn games(g: &GameState) {
+    println!(
+        "{:<25} {:>9} {:>7}  {:<10} map",
+        "start", "duration", "players", "format"
+    );
+    for game in &g.games {
+        let duration = match game.end_time {
+            Some(end) => {
+                let s = end.signed_duration_since(game.start_time).num_seconds();
+                format!("{}m{:02}s", s / 60, s % 60)
             }
-        }
-        None => None,
+            None => String::from("-"),
+        };
+        println!(
+            "{:<25} {:>9} {:>7}  {:<10} {}",
+            display_time(game, &game.start_time).format("%Y-%m-%d %H:%M:%S %:z"),
+            duration,
+            game.players.len(),
+            game.generation.as_deref().unwrap_or("-"),
+            game.map
+        );
+    }
+}
+
+fn merge(statefile: &Statefile, config: &Config, others: &[PathBuf]) {
+    let _lock = lock_statefile(&statefile.path).expect("Error locking statefile");
+    let mut g = load_or_create_statefile(statefile, config);
+    for path in others {
+        let contents = fs::read_to_string(path).expect("Error reading statefile");
+        let other = load_state(&contents).expect("Error reading statefile");
+        let summary = merge_state(&mut g, &other);
+        println!(
+            "{}: added {} games, skipped {} we already had, reconciled {} identities",
+            path.display(),
+            summary.games_added,
+            summary.games_skipped,
+            summary.identities_renamed
+        );
     }
+    save_state(statefile, &g);
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-struct PlayerOutput {
-    name: String,
-    count_kills: u32,
-    count_killed: u32,
-    count_revives: u32,
-    count_revived: u32,
-    kills: HashMap<String, u32>,
-    killed_by: HashMap<String, u32>,
-    revives: HashMap<String, u32>,
-    revived_by: HashMap<String, u32>,
-    classes: HashSet<String>,
+fn export(g: &GameState, catalog: &WeaponCatalog, dir: &Path, format: ExportFormat) {
+    let paths =
+        write_export(dir, &export_tables(g, catalog), format).expect("Error writing export");
+    for path in paths {
+        println!("wrote {}", path.display());
+    }
 }
 
-fn sum_map(m: &HashMap<String, u32>) -> u32 {
-    let mut res: u32 = 0;
+fn site(g: &GameState, catalog: &WeaponCatalog, dir: &Path, templates: Option<&Path>) {
+    let env = site_templates(templates).expect("Error reading templates");
+    let pages = write_site(g, catalog, &env, dir).expect("Error writing site");
+    println!("wrote {} pages to {}", pages, dir.display());
+}
 
-    for (_n, c) in m {
-        res += c;
+// A date on its own means midnight at the start of it, in the display zone.
+fn parse_cutoff(s: &str, zone: Tz) -> DateTime<FixedOffset> {
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return t;
     }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .expect("expected a date like 2021-06-01 or 2021-06-01T00:00:00Z");
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+    zone.from_local_datetime(&midnight)
+        .earliest()
+        .expect("that date has no midnight in the display zone")
+        .fixed_offset()
+}
 
-    res
+fn prune(statefile: &Statefile, config: &Config, before: &str) {
+    let _lock = lock_statefile(&statefile.path).expect("Error locking statefile");
+    let mut g = load_statefile(statefile, config);
+    let cutoff = parse_cutoff(before, g.timezones.display_zone());
+    let n = prune_games(&mut g, &cutoff);
+    println!("dropped {} games that started before {}", n, cutoff);
+    save_state(statefile, &g);
 }
 
-fn print_lifetime_stats(g: &GameState) {
-    let mut lifetime_players: HashMap<String, PlayerOutput> = HashMap::new();
+// Options that apply to every command.
+#[derive(Args)]
+struct Config {
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Patterns to parse log lines with, instead of the built-in ones"
+    )]
+    patterns: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Which zone each log was written in, and which to show times in"
+    )]
+    timezones: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Identity merges and splits to apply"
+    )]
+    aliases: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Weapons to add to the built-in catalog"
+    )]
+    weapons: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        default_value_t = DEFAULT_BACKUPS,
+        help = "How many old statefiles to keep when saving"
+    )]
+    backups: usize,
+}
 
-    for game in &g.games {
-        for (player_name, player_state) in &game.players {
-            let updt = match lifetime_players.get(player_name) {
-                Some(p) => {
-                    // Merge everything.
-                    let mut new_kills = p.kills.clone();
-                    for (n, c) in &player_state.players_killed {
-                        *new_kills.entry(n.clone()).or_insert(0) += c;
-                    }
-                    let mut new_kills_by = p.killed_by.clone();
-                    for (n, c) in &player_state.players_killed_by {
-                        *new_kills_by.entry(n.clone()).or_insert(0) += c;
-                    }
-                    let mut new_revives = p.revives.clone();
-                    for (n, c) in &player_state.players_revived {
-                        *new_revives.entry(n.clone()).or_insert(0) += c;
-                    }
-                    let mut new_revived_by = p.revived_by.clone();
-                    for (n, c) in &player_state.players_revived_by {
-                        *new_revived_by.entry(n.clone()).or_insert(0) += c;
-                    }
-
-                    PlayerOutput {
-                        count_kills: p.count_kills + sum_map(&player_state.players_killed),
-                        count_killed: p.count_killed + sum_map(&player_state.players_killed_by),
-                        count_revived: p.count_revived + sum_map(&player_state.players_revived_by),
-                        count_revives: p.count_revives + sum_map(&player_state.players_revived),
-                        kills: new_kills,
-                        killed_by: new_kills_by,
-                        revives: new_revives,
-                        revived_by: new_revived_by,
-                        classes: p
-                            .classes
-                            .union(&player_state.classes_played)
-                            .cloned()
-                            .collect(),
-                        ..p.clone()
-                    }
-                }
-                None => PlayerOutput {
-                    name: player_name.clone(),
-                    count_kills: sum_map(&player_state.players_killed),
-                    count_killed: sum_map(&player_state.players_killed_by),
-                    count_revived: sum_map(&player_state.players_revived_by),
-                    count_revives: sum_map(&player_state.players_revived),
-                    kills: player_state.players_killed.clone(),
-                    killed_by: player_state.players_killed_by.clone(),
-                    revives: player_state.players_revived.clone(),
-                    revived_by: player_state.players_revived_by.clone(),
-                    classes: player_state.classes_played.clone(),
-                },
-            };
-            lifetime_players.insert(String::from(player_name), updt);
+impl Config {
+    fn patterns(&self) -> Patterns {
+        match &self.patterns {
+            Some(path) => Patterns::from_file(path).expect("Error reading pattern file"),
+            None => Patterns::builtin(),
         }
     }
 
-    println!(
-        "{}",
-        serde_json::to_string(&lifetime_players).expect("serialization error")
-    );
-}
+    fn timezones(&self) -> Timezones {
+        match &self.timezones {
+            Some(path) => Timezones::from_file(path).expect("Error reading timezone file"),
+            None => Timezones::default(),
+        }
+    }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        panic!("expected statefile logfile");
+    fn overrides(&self) -> IdentityOverrides {
+        match &self.aliases {
+            Some(path) => IdentityOverrides::from_file(path).expect("Error reading alias file"),
+            None => IdentityOverrides::default(),
+        }
     }
 
-    let statefile = &args[1];
-    let logfile = &args[2];
+    fn catalog(&self) -> WeaponCatalog {
+        let mut catalog = WeaponCatalog::builtin();
+        if let Some(path) = &self.weapons {
+            catalog
+                .extend_from_file(path)
+                .expect("Error reading weapon catalog");
+        }
+        catalog
+    }
 
-    let mut g = match fs::read_to_string(statefile) {
-        Ok(statefile_lines) => serde_json::from_str::<GameState>(&statefile_lines).unwrap(),
-        Err(_e) => GameState {
-            games: Vec::new(),
-            current_game_start_time: get_dt("1941.12.07-07.00.00:000").unwrap(),
-            last_timestamp: get_dt("1941.12.7-07.00.00:000").unwrap(),
-            player_names: Vec::new(),
-        },
-    };
+    fn statefile(&self, path: &Path) -> Statefile {
+        Statefile {
+            path: path.to_path_buf(),
+            backups: self.backups,
+        }
+    }
+}
 
-    let logfile_contents = fs::read_to_string(logfile).expect("Error opening log file");
-    let lines: Vec<&str> = logfile_contents.split("\n").collect();
+#[derive(Args)]
+struct IngestOptions {
+    #[arg(required = true, help = "Log files, directories or globs")]
+    logs: Vec<String>,
+    #[arg(long, help = "Keep following the newest log as the server writes it")]
+    follow: bool,
+    #[arg(long, help = "Stop at the first line that can't be parsed or applied")]
+    strict: bool,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Write a report into DIR for each match that ends"
+    )]
+    reports: Option<PathBuf>,
+}
 
-    let pb = ProgressBar::new(logfile_contents.len() as u64);
-    pb.set_style(ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-        .progress_chars("#>-"));
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Read logs into the statefile")]
+    Ingest {
+        statefile: PathBuf,
+        #[command(flatten)]
+        opts: IngestOptions,
+    },
+    #[command(about = "Print the player and weapon leaderboards")]
+    Report { statefile: PathBuf },
+    #[command(about = "Print one player's lifetime stats")]
+    Player {
+        statefile: PathBuf,
+        #[arg(help = "The name they are listed under, their identity, or any name they've used")]
+        name: String,
+    },
+    #[command(about = "List the matches in the statefile")]
+    Games { statefile: PathBuf },
+    #[command(about = "Add the games from other statefiles to this one")]
+    Merge {
+        statefile: PathBuf,
+        #[arg(required = true)]
+        others: Vec<PathBuf>,
+    },
+    #[command(about = "Drop games that started before a date")]
+    Prune {
+        statefile: PathBuf,
+        #[arg(
+            long,
+            value_name = "DATE",
+            help = "A date, taken in the display zone, or an RFC 3339 time"
+        )]
+        before: String,
+    },
+    #[command(about = "Write the stats out as flat tables for spreadsheets")]
+    Export {
+        statefile: PathBuf,
+        #[arg(help = "Where to write players, kills, games and weapons tables")]
+        dir: PathBuf,
+        #[arg(long, default_value = "csv", help = "csv, ndjson or json")]
+        format: ExportFormat,
+    },
+    #[command(about = "Render the stats as a static HTML site")]
+    Site {
+        statefile: PathBuf,
+        #[arg(help = "Where to write the site")]
+        dir: PathBuf,
+        #[arg(
+            long,
+            value_name = "DIR",
+            help = "Templates to use instead of the built-in ones of the same name"
+        )]
+        templates: Option<PathBuf>,
+    },
+    #[command(about = "Report how much of the logs the patterns understand")]
+    Coverage {
+        #[arg(required = true)]
+        logs: Vec<String>,
+    },
+    #[command(about = "Upgrade a statefile from an older squadlog")]
+    Migrate {
+        statefile: PathBuf,
+        #[arg(long, help = "Only show what would change")]
+        dry_run: bool,
+    },
+    #[command(about = "List the statefile's backups, or put one back")]
+    Restore {
+        statefile: PathBuf,
+        #[arg(help = "Which backup to restore, 1 being the most recent")]
+        backup: Option<usize>,
+    },
+    #[command(about = "Recompute the statefile from its event log")]
+    Rebuild { statefile: PathBuf },
+}
 
-    let r = Regexes {
-        logsquad_damaged: Regex::new(r"Player:(.*) ActualDamage=(\d+\.\d+) from (.*) caused by (.*)$").unwrap(),
-        logsquad_revived: Regex::new(r"(.*) has revived (.*)\.$").unwrap(),
-        line: Regex::new(r"^\[(\d+.\d+.\d+-\d+.\d+.\d+:\d+)\]\[.*\](\w+): (.*)").unwrap(),
-        trace_role: Regex::new(r"\[DedicatedServer\]ASQPlayerController::SetCurrentRole\(\): On Server PC=(.*) NewRole=(.*)").unwrap(),
-        trace_down: Regex::new(r"\[DedicatedServer\]ASQSoldier::Wound\(\): Player:(.*) KillingDamage=(\d+.\d+) from (.*) caused by (.*)").unwrap(),
-        trace_statechange: Regex::new(r"\[DedicatedServer\]ASQPlayerController::ChangeState\(\): PC=(.*) OldState=(.*) NewState=(.*)").unwrap(),
-        game_state_change: Regex::new(r"Match State Changed from (\w+) to (\w+)$").unwrap(),
-        world_state_change: Regex::new(r"StartLoadingDestination to: /Game/Maps/(.*)").unwrap()
-    };
+#[derive(Parser)]
+#[command(
+    name = "squadlog",
+    version,
+    about = "Player stats from Squad server logs"
+)]
+struct Cli {
+    #[command(flatten)]
+    config: Config,
+    #[command(subcommand)]
+    command: Command,
+}
 
-    let mut new: u64 = 0;
-    for line in &lines {
-        new = new + (line.len() as u64);
-        match parse_line(line, &g, &r) {
-            Some(new_g) => g = new_g,
-            None => (),
+fn main() {
+    let cli = Cli::parse();
+    let config = &cli.config;
+    match &cli.command {
+        Command::Ingest { statefile, opts } => {
+            ingest_command(&config.statefile(statefile), config, opts)
+        }
+        Command::Report { statefile } => {
+            let g = load_statefile(&config.statefile(statefile), config);
+            print_lifetime_stats(&g, &config.catalog());
+        }
+        Command::Player { statefile, name } => {
+            let g = load_statefile(&config.statefile(statefile), config);
+            player(&g, &config.catalog(), name);
+        }
+        Command::Games { statefile } => {
+            games(&load_statefile(&config.statefile(statefile), config))
+        }
+        Command::Merge { statefile, others } => merge(&config.statefile(statefile), config, others),
+        Command::Prune { statefile, before } => prune(&config.statefile(statefile), config, before),
+        Command::Export {
+            statefile,
+            dir,
+            format,
+        } => {
+            let g = load_statefile(&config.statefile(statefile), config);
+            export(&g, &config.catalog(), dir, *format);
+        }
+        Command::Site {
+            statefile,
+            dir,
+            templates,
+        } => {
+            let g = load_statefile(&config.statefile(statefile), config);
+            site(&g, &config.catalog(), dir, templates.as_deref());
+        }
+        Command::Coverage { logs } => coverage(logs, &config.patterns(), &config.timezones()),
+        Command::Migrate { statefile, dry_run } => {
+            let statefile = config.statefile(statefile);
+            let _lock = lock_statefile(&statefile.path).expect("Error locking statefile");
+            migrate_statefile(&statefile, *dry_run);
+        }
+        Command::Restore { statefile, backup } => {
+            let statefile = config.statefile(statefile);
+            let _lock = lock_statefile(&statefile.path).expect("Error locking statefile");
+            restore(&statefile, *backup);
+        }
+        Command::Rebuild { statefile } => {
+            let statefile = config.statefile(statefile);
+            let _lock = lock_statefile(&statefile.path).expect("Error locking statefile");
+            rebuild_statefile(&statefile, config.overrides(), config.timezones());
         }
-        pb.set_position(new);
     }
-
-    print_lifetime_stats(&g);
-    fs::write(
-        statefile,
-        serde_json::to_string(&g).expect("serialization error"),
-    )
-    .expect("IO");
 }
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use state::{Game, GameState};
+
+// What merge_state did.
+#[derive(Debug, Clone, Default)]
+pub struct MergeSummary {
+    pub games_added: usize,
+    // Games both states had, from the same log read into each.
+    pub games_skipped: usize,
+    pub identities_renamed: usize,
+}
+
+fn rename_identities(game: &mut Game, renames: &HashMap<String, String>) {
+    for player in game.players.values_mut() {
+        if let Some(new) = player.identity.as_ref().and_then(|i| renames.get(i)) {
+            player.identity = Some(new.clone());
+        }
+    }
+}
+
+// Fold other into g, as when two servers, or two copies of one, kept their own
+// statefiles. Games are told apart by when they started, and the identities
+// of the two are reconciled by online ID. Where g and other both have
+// something, like a checkpoint for the same log, g's is kept.
+pub fn merge_state(g: &mut GameState, other: &GameState) -> MergeSummary {
+    let renames = g.identities.absorb(&other.identities);
+    let mut summary = MergeSummary {
+        identities_renamed: renames.len(),
+        ..MergeSummary::default()
+    };
+
+    for game in &other.games {
+        if g.games
+            .binary_search_by_key(&game.start_time, |t| t.start_time)
+            .is_ok()
+        {
+            summary.games_skipped += 1;
+            continue;
+        }
+        let mut game = game.clone();
+        rename_identities(&mut game, &renames);
+        let idx = g.games.partition_point(|t| t.start_time <= game.start_time);
+        g.games.insert(idx, game);
+        summary.games_added += 1;
+    }
+
+    for file in &other.files {
+        if !g.files.iter().any(|f| f.path == file.path) {
+            g.files.push(file.clone());
+        }
+    }
+    if other.last_timestamp > g.last_timestamp {
+        g.last_timestamp = other.last_timestamp;
+    }
+    // Our event log doesn't have the games we took in.
+    if summary.games_added > 0 {
+        g.events_complete = false;
+    }
+    summary
+}
@@ -0,0 +1,179 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use identity::IdentityRegistry;
+
+// An admin's corrections to who is who, kept in a TOML file like:
+//
+//   [[merge]]
+//   identity = "alice"
+//   members = ["Alice", "[TAG] Alice", "steam:76561190000000000"]
+//
+//   [[split]]
+//   name = "Bob"
+//   identity = "bob-2021"
+//   from = "2021-01-01T00:00:00Z"
+//
+// Members of a merge can be names, online IDs or identities. A split gives
+// whoever played as name between from and until (either can be left out)
+// an identity of their own. Overrides aren't saved in the statefile; they
+// are applied whenever we work out who a player is, so editing the file and
+// rerunning is enough to re-aggregate.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct IdentityOverrides {
+    #[serde(default)]
+    pub merge: Vec<Merge>,
+    #[serde(default)]
+    pub split: Vec<Split>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Merge {
+    pub identity: String,
+    pub members: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Split {
+    pub name: String,
+    pub identity: String,
+    pub from: Option<DateTime<FixedOffset>>,
+    pub until: Option<DateTime<FixedOffset>>,
+}
+
+impl Split {
+    fn covers(&self, name: &str, when: &DateTime<FixedOffset>) -> bool {
+        self.name == name
+            && self.from.is_none_or(|from| from <= *when)
+            && self.until.is_none_or(|until| *when < until)
+    }
+}
+
+impl Merge {
+    // Whether this merge takes in a player seen as name, whose identity
+    // we've worked out so far is identity. Names only count if the player
+    // wasn't split off.
+    fn takes(
+        &self,
+        registry: &IdentityRegistry,
+        name: Option<&str>,
+        identity: Option<&String>,
+    ) -> bool {
+        self.members.iter().any(|m| {
+            Some(m.as_str()) == name
+                || (identity.is_some() && (Some(m) == identity || registry.ids.get(m) == identity))
+        })
+    }
+}
+
+impl IdentityOverrides {
+    pub fn from_file(path: &Path) -> io::Result<IdentityOverrides> {
+        let contents = fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Who a player seen as name at when really is, given the identity the
+    // registry has for them. None means they should be counted by name.
+    pub fn identity_of(
+        &self,
+        registry: &IdentityRegistry,
+        name: &str,
+        identity: Option<&String>,
+        when: &DateTime<FixedOffset>,
+    ) -> Option<String> {
+        let (name, identity) = match self.split.iter().find(|s| s.covers(name, when)) {
+            Some(s) => (None, Some(s.identity.clone())),
+            None => (Some(name), identity.cloned()),
+        };
+
+        match self
+            .merge
+            .iter()
+            .find(|m| m.takes(registry, name, identity.as_ref()))
+        {
+            Some(m) => Some(m.identity.clone()),
+            None => identity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn overrides() -> IdentityOverrides {
+        toml::from_str(
+            r#"
+            [[merge]]
+            identity = "alice"
+            members = ["Alice", "[TAG] Alice", "steam:111"]
+
+            [[merge]]
+            identity = "bob"
+            members = ["Bob"]
+
+            [[split]]
+            name = "Bob"
+            identity = "bob-2021"
+            from = "2021-01-01T00:00:00Z"
+            until = "2022-01-01T00:00:00Z"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn without_overrides_the_registry_decides() {
+        let registry = IdentityRegistry::default();
+        let none = IdentityOverrides::default();
+        let eos = String::from("eos:abc");
+        let when = at("2021-06-01T00:00:00Z");
+        assert_eq!(
+            none.identity_of(&registry, "Alice", Some(&eos), &when),
+            Some(eos.clone())
+        );
+        assert_eq!(none.identity_of(&registry, "Alice", None, &when), None);
+    }
+
+    #[test]
+    fn merges_by_name_id_or_identity() {
+        let mut registry = IdentityRegistry::default();
+        registry
+            .ids
+            .insert(String::from("steam:111"), String::from("eos:abc"));
+        let o = overrides();
+        let when = at("2021-06-01T00:00:00Z");
+        let alice = Some(String::from("alice"));
+
+        assert_eq!(o.identity_of(&registry, "[TAG] Alice", None, &when), alice);
+        // Someone we only know by online ID, under a name the merge doesn't
+        // list.
+        let eos = String::from("eos:abc");
+        assert_eq!(o.identity_of(&registry, "Al", Some(&eos), &when), alice);
+        let other = String::from("eos:def");
+        assert_eq!(
+            o.identity_of(&registry, "Al", Some(&other), &when),
+            Some(other.clone())
+        );
+    }
+
+    #[test]
+    fn splits_a_name_for_a_time() {
+        let registry = IdentityRegistry::default();
+        let o = overrides();
+        let bob = |when: &str| o.identity_of(&registry, "Bob", None, &at(when));
+
+        assert_eq!(bob("2020-06-01T00:00:00Z"), Some(String::from("bob")));
+        // Whoever was split off isn't merged by the name they played under.
+        assert_eq!(bob("2021-01-01T00:00:00Z"), Some(String::from("bob-2021")));
+        assert_eq!(bob("2021-06-01T00:00:00Z"), Some(String::from("bob-2021")));
+        assert_eq!(bob("2022-01-01T00:00:00Z"), Some(String::from("bob")));
+    }
+}
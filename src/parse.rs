@@ -0,0 +1,557 @@
+use chrono::{DateTime, FixedOffset, LocalResult, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use regex::{Captures, Regex};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io;
+use std::str::FromStr;
+
+use error::LogError;
+use event::SquadEvent;
+use identity::{parse_online_ids, split_online_ids};
+use patterns::{invalid_patterns, PatternDef, PatternFile, PatternKind, PatternSetDef};
+
+// Squad timestamps carry no zone. This reads them as UTC.
+pub fn get_dt(s: &str) -> Option<DateTime<FixedOffset>> {
+    match get_local_dt(s, Tz::UTC) {
+        LocalResult::Single(t) => Some(t),
+        _ => None,
+    }
+}
+
+// Read a timestamp as local time in zone. Around DST changes that can give
+// two instants or none.
+pub fn get_local_dt(s: &str, zone: Tz) -> LocalResult<DateTime<FixedOffset>> {
+    match NaiveDateTime::parse_from_str(s, "%Y.%m.%d-%H.%M.%S:%3f") {
+        Ok(naive) => zone.from_local_datetime(&naive).map(|t| t.fixed_offset()),
+        Err(_e) => LocalResult::None,
+    }
+}
+
+// Parse routines.
+
+// Compile regex, checking that every field we need has a capture group and
+// that every group named for a field exists.
+fn compile(regex: &str, fields: &HashMap<String, usize>, required: &[&str]) -> io::Result<Regex> {
+    let compiled = Regex::new(regex).map_err(|e| invalid_patterns(format!("{}: {}", regex, e)))?;
+    for (field, group) in fields {
+        if *group >= compiled.captures_len() {
+            return Err(invalid_patterns(format!(
+                "{}: field {} is group {}, but there are only {} groups",
+                regex,
+                field,
+                group,
+                compiled.captures_len() - 1
+            )));
+        }
+    }
+    for field in required {
+        if !fields.contains_key(*field) {
+            return Err(invalid_patterns(format!(
+                "{}: no group for field {}",
+                regex, field
+            )));
+        }
+    }
+    Ok(compiled)
+}
+
+// A compiled pattern, and which capture group holds each of its fields.
+#[derive(Debug, Clone)]
+struct Pattern {
+    kind: PatternKind,
+    regex: Regex,
+    fields: HashMap<String, usize>,
+}
+
+// The fields of one match of a Pattern.
+struct Fields<'a, 't> {
+    caps: Captures<'t>,
+    fields: &'a HashMap<String, usize>,
+}
+
+impl<'a, 't> Fields<'a, 't> {
+    // Every required field is checked to have a group when the pattern is
+    // compiled, but an optional field or group can still be missing.
+    fn get(&self, field: &str) -> &'t str {
+        self.fields
+            .get(field)
+            .and_then(|group| self.caps.get(*group))
+            .map_or("", |m| m.as_str())
+    }
+}
+
+impl Pattern {
+    fn compile(def: &PatternDef) -> io::Result<Pattern> {
+        Ok(Pattern {
+            kind: def.kind,
+            regex: compile(&def.regex, &def.fields, def.kind.fields())?,
+            fields: def.fields.clone(),
+        })
+    }
+
+    fn captures<'a, 't>(&'a self, text: &'t str) -> Option<Fields<'a, 't>> {
+        Some(Fields {
+            caps: self.regex.captures(text)?,
+            fields: &self.fields,
+        })
+    }
+}
+
+// Turns raw log lines into SquadEvents using one set of patterns. Building
+// one compiles every pattern, so make one and reuse it for the whole log.
+#[derive(Debug, Clone)]
+pub struct Parser {
+    // The name of the pattern set, which is the log format generation.
+    generation: String,
+    // The zone the log's timestamps are in, and the last timestamp we read,
+    // to tell which pass through a repeated hour a line is from.
+    zone: Tz,
+    last: Cell<Option<DateTime<FixedOffset>>>,
+    line: Regex,
+    line_fields: HashMap<String, usize>,
+    // In the order they appear in the pattern set. Every pattern that matches
+    // a line adds its events, in that order.
+    by_category: HashMap<String, Vec<Pattern>>,
+}
+
+// A line that had a valid timestamp, and whatever events we recognized in it.
+// Lines we don't understand still carry their timestamp with no events.
+#[derive(Debug, Clone)]
+pub struct ParsedLine {
+    pub timestamp: DateTime<FixedOffset>,
+    pub events: Vec<SquadEvent>,
+}
+
+fn parse_float(s: &str) -> f32 {
+    f32::from_str(s).unwrap_or(0.0)
+}
+
+impl Parser {
+    // A parser for the default set of the built-in patterns.
+    pub fn new() -> Parser {
+        let builtin = PatternFile::builtin();
+        Parser::from_set(
+            builtin
+                .default_set()
+                .expect("built-in patterns have no default set"),
+        )
+        .expect("built-in patterns are invalid")
+    }
+
+    pub fn from_set(set: &PatternSetDef) -> io::Result<Parser> {
+        let line = compile(
+            &set.line.regex,
+            &set.line.fields,
+            &["timestamp", "category", "message"],
+        )?;
+
+        let mut by_category: HashMap<String, Vec<Pattern>> = HashMap::new();
+        for def in &set.patterns {
+            by_category
+                .entry(def.category.clone())
+                .or_default()
+                .push(Pattern::compile(def)?);
+        }
+
+        Ok(Parser {
+            generation: set.name.clone(),
+            zone: Tz::UTC,
+            last: Cell::new(None),
+            line,
+            line_fields: set.line.fields.clone(),
+            by_category,
+        })
+    }
+
+    pub fn generation(&self) -> &str {
+        &self.generation
+    }
+
+    // A parser like this one, for a log written in zone. Make one per log.
+    pub fn in_zone(&self, zone: Tz) -> Parser {
+        Parser {
+            zone,
+            last: Cell::new(None),
+            ..self.clone()
+        }
+    }
+
+    pub fn zone(&self) -> Tz {
+        self.zone
+    }
+
+    // When the clocks go back an hour of local time happens twice. The log
+    // only moves forward, so once a line's earlier reading would be before
+    // the last line we read, we are on the second pass.
+    fn timestamp(&self, s: &str) -> Option<DateTime<FixedOffset>> {
+        let t = match get_local_dt(s, self.zone) {
+            LocalResult::Single(t) => t,
+            LocalResult::Ambiguous(early, late) => match self.last.get() {
+                Some(last) if early < last => late,
+                _ => early,
+            },
+            LocalResult::None => return None,
+        };
+        self.last.set(Some(t));
+        Some(t)
+    }
+
+    // Whether we have any patterns for lines in category.
+    pub fn handles(&self, category: &str) -> bool {
+        self.by_category.contains_key(category)
+    }
+
+    // Newer logs print online IDs after player names. Strip them off the name,
+    // and if there were any, note them in a PlayerIdentified event. Controller
+    // IDs get printed in place of names sometimes; those aren't names, so we
+    // don't record them.
+    fn player_name(
+        &self,
+        timestamp: &DateTime<FixedOffset>,
+        raw: &str,
+        events: &mut Vec<SquadEvent>,
+    ) -> String {
+        let (name, ids) = split_online_ids(raw);
+        if !ids.is_empty() && name != "nullptr" && !name.starts_with("BP_PlayerController") {
+            events.push(SquadEvent::PlayerIdentified {
+                timestamp: *timestamp,
+                name: name.clone(),
+                ids,
+            });
+        }
+        name
+    }
+
+    // Add the event for one pattern match, after any PlayerIdentified events
+    // for the names in it.
+    fn push_event(
+        &self,
+        timestamp: &DateTime<FixedOffset>,
+        kind: PatternKind,
+        x: &Fields,
+        events: &mut Vec<SquadEvent>,
+    ) {
+        let timestamp = *timestamp;
+        let event = match kind {
+            PatternKind::Revived => SquadEvent::Revived {
+                timestamp,
+                reviver: self.player_name(&timestamp, x.get("reviver"), events),
+                revivee: self.player_name(&timestamp, x.get("revivee"), events),
+            },
+            PatternKind::Damaged => SquadEvent::Damaged {
+                timestamp,
+                victim: self.player_name(&timestamp, x.get("victim"), events),
+                damage: parse_float(x.get("damage")),
+                attacker: self.player_name(&timestamp, x.get("attacker"), events),
+                weapon: String::from(x.get("weapon")),
+            },
+            PatternKind::PlayerLoggedIn => SquadEvent::PlayerLoggedIn {
+                timestamp,
+                controller: String::from(x.get("controller")),
+                ids: parse_online_ids(x.get("online_ids")),
+            },
+            PatternKind::RoleChanged => SquadEvent::RoleChanged {
+                timestamp,
+                player: self.player_name(&timestamp, x.get("player"), events),
+                role: String::from(x.get("role")),
+            },
+            PatternKind::Wounded => SquadEvent::Wounded {
+                timestamp,
+                victim: self.player_name(&timestamp, x.get("victim"), events),
+                damage: parse_float(x.get("damage")),
+                attacker: self.player_name(&timestamp, x.get("attacker"), events),
+                weapon: String::from(x.get("weapon")),
+            },
+            PatternKind::Died => SquadEvent::Died {
+                timestamp,
+                victim: self.player_name(&timestamp, x.get("victim"), events),
+                damage: parse_float(x.get("damage")),
+                attacker: self.player_name(&timestamp, x.get("attacker"), events),
+                weapon: String::from(x.get("weapon")),
+            },
+            PatternKind::ControllerStateChanged => SquadEvent::ControllerStateChanged {
+                timestamp,
+                controller: self.player_name(&timestamp, x.get("controller"), events),
+                old_state: String::from(x.get("old_state")),
+                new_state: String::from(x.get("new_state")),
+            },
+            PatternKind::MatchStateChanged => SquadEvent::MatchStateChanged {
+                timestamp,
+                from: String::from(x.get("from")),
+                to: String::from(x.get("to")),
+            },
+            PatternKind::MapLoading => SquadEvent::MapLoading {
+                timestamp,
+                map: String::from(x.get("map")),
+            },
+            PatternKind::PlayerJoined => SquadEvent::PlayerJoined {
+                timestamp,
+                name: String::from(x.get("name")),
+            },
+        };
+        events.push(event);
+    }
+
+    // Split a log line into its category and message, or None if it doesn't
+    // look like a timestamped log line at all.
+    pub fn split_line<'a>(&self, line: &'a str) -> Option<(&'a str, &'a str)> {
+        let c = self.line.captures(line)?;
+        Some((
+            c.get(self.line_fields["category"])?.as_str(),
+            c.get(self.line_fields["message"])?.as_str(),
+        ))
+    }
+
+    // Parse one line of the log. Returns None if the line doesn't look like a
+    // timestamped log line at all.
+    pub fn parse_line(&self, line: &str) -> Result<Option<ParsedLine>, LogError> {
+        let c = match self.line.captures(line) {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let field = |name: &str| c.get(self.line_fields[name]).map_or("", |m| m.as_str());
+        let timestamp = match self.timestamp(field("timestamp")) {
+            Some(t) => t,
+            None => return Err(LogError::BadTimestamp(String::from(field("timestamp")))),
+        };
+
+        let mut events = Vec::new();
+        if let Some(patterns) = self.by_category.get(field("category")) {
+            let msg = field("message");
+            for pattern in patterns {
+                if let Some(x) = pattern.captures(msg) {
+                    self.push_event(&timestamp, pattern.kind, &x, &mut events);
+                }
+            }
+        }
+
+        Ok(Some(ParsedLine { timestamp, events }))
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use identity::OnlineIds;
+
+    fn at(s: &str) -> DateTime<FixedOffset> {
+        get_dt(s).unwrap()
+    }
+
+    fn events(line: &str) -> Vec<SquadEvent> {
+        Parser::new().parse_line(line).unwrap().unwrap().events
+    }
+
+    #[test]
+    fn parses_each_kind_of_line() {
+        let t = at("2026.10.01-10.00.00:000");
+        let line = |category: &str, msg: &str| {
+            format!("[2026.10.01-10.00.00:000][  1]{}: {}", category, msg)
+        };
+
+        assert_eq!(
+            events(&line("LogSquad", "Alice has revived Bob.")),
+            vec![SquadEvent::Revived {
+                timestamp: t,
+                reviver: String::from("Alice"),
+                revivee: String::from("Bob"),
+            }]
+        );
+        assert_eq!(
+            events(&line(
+                "LogSquad",
+                "Player:Bob ActualDamage=60.000000 from Alice caused by BP_M4_C_1"
+            )),
+            vec![SquadEvent::Damaged {
+                timestamp: t,
+                victim: String::from("Bob"),
+                damage: 60.0,
+                attacker: String::from("Alice"),
+                weapon: String::from("BP_M4_C_1"),
+            }]
+        );
+        assert_eq!(
+            events(&line(
+                "LogSquad",
+                "PostLogin: NewPlayer: BP_PlayerController_C /Game/Maps/Narva.Narva:PersistentLevel.BP_PlayerController_C_2147 (IP: 10.0.0.1 | Online IDs: EOS: abc steam: 123)"
+            )),
+            vec![SquadEvent::PlayerLoggedIn {
+                timestamp: t,
+                controller: String::from("BP_PlayerController_C_2147"),
+                ids: OnlineIds {
+                    eos: Some(String::from("abc")),
+                    steam: Some(String::from("123")),
+                },
+            }]
+        );
+        assert_eq!(
+            events(&line(
+                "LogSquadTrace",
+                "[DedicatedServer]ASQPlayerController::SetCurrentRole(): On Server PC=Alice NewRole=USA_Rifleman_01"
+            )),
+            vec![SquadEvent::RoleChanged {
+                timestamp: t,
+                player: String::from("Alice"),
+                role: String::from("USA_Rifleman_01"),
+            }]
+        );
+        assert_eq!(
+            events(&line(
+                "LogSquadTrace",
+                "[DedicatedServer]ASQSoldier::Wound(): Player:Bob KillingDamage=60.000000 from Alice caused by BP_M4_C_1"
+            )),
+            vec![SquadEvent::Wounded {
+                timestamp: t,
+                victim: String::from("Bob"),
+                damage: 60.0,
+                attacker: String::from("Alice"),
+                weapon: String::from("BP_M4_C_1"),
+            }]
+        );
+        assert_eq!(
+            events(&line(
+                "LogSquadTrace",
+                "[DedicatedServer]ASQPlayerController::ChangeState(): PC=Alice OldState=Inactive NewState=Playing"
+            )),
+            vec![SquadEvent::ControllerStateChanged {
+                timestamp: t,
+                controller: String::from("Alice"),
+                old_state: String::from("Inactive"),
+                new_state: String::from("Playing"),
+            }]
+        );
+        assert_eq!(
+            events(&line(
+                "LogGameState",
+                "Match State Changed from InProgress to WaitingPostMatch"
+            )),
+            vec![SquadEvent::MatchStateChanged {
+                timestamp: t,
+                from: String::from("InProgress"),
+                to: String::from("WaitingPostMatch"),
+            }]
+        );
+        assert_eq!(
+            events(&line(
+                "LogWorld",
+                "StartLoadingDestination to: /Game/Maps/Narva/Gameplay_Layers/Narva_RAAS_v1"
+            )),
+            vec![SquadEvent::MapLoading {
+                timestamp: t,
+                map: String::from("Narva/Gameplay_Layers/Narva_RAAS_v1"),
+            }]
+        );
+        assert_eq!(
+            events(&line("LogNet", "Join succeeded: Alice")),
+            vec![SquadEvent::PlayerJoined {
+                timestamp: t,
+                name: String::from("Alice"),
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_the_sign_of_damage() {
+        let parsed = events(
+            "[2026.10.01-10.00.00:000][  1]LogSquad: Player:Bob ActualDamage=-12.500000 from Alice caused by BP_M4_C_1",
+        );
+        match &parsed[..] {
+            [SquadEvent::Damaged { damage, .. }] => assert_eq!(*damage, -12.5),
+            other => panic!("expected one Damaged event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn identifies_names_printed_with_online_ids() {
+        let parsed = events(
+            "[2026.10.01-10.00.00:000][  1]LogSquad: Player:Bob ActualDamage=60.000000 from Alice (Online IDs: EOS: abc steam: 123 | Player Controller ID: BP_PlayerController_C_2147) caused by BP_M4_C_1",
+        );
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(
+            parsed[0],
+            SquadEvent::PlayerIdentified {
+                timestamp: at("2026.10.01-10.00.00:000"),
+                name: String::from("Alice"),
+                ids: OnlineIds {
+                    eos: Some(String::from("abc")),
+                    steam: Some(String::from("123")),
+                },
+            }
+        );
+        match &parsed[1] {
+            SquadEvent::Damaged { attacker, .. } => assert_eq!(attacker, "Alice"),
+            other => panic!("expected a Damaged event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lines_it_does_not_understand() {
+        let parser = Parser::new();
+        assert!(parser
+            .parse_line("Log file open, 10/01/26")
+            .unwrap()
+            .is_none());
+        let parsed = parser
+            .parse_line("[2026.10.01-10.00.00:000][  1]LogSquad: Something else entirely")
+            .unwrap()
+            .unwrap();
+        assert!(parsed.events.is_empty());
+        match parser.parse_line("[2026.19.01-10.00.00:000][  1]LogSquad: x") {
+            Err(e) => assert_eq!(
+                e,
+                LogError::BadTimestamp(String::from("2026.19.01-10.00.00:000"))
+            ),
+            Ok(_parsed) => panic!("expected a bad timestamp"),
+        }
+    }
+
+    #[test]
+    fn reads_through_a_dst_fold() {
+        // Berlin goes from CEST back to CET at 03:00 on 2026-10-25, so 02:00
+        // to 03:00 happens twice.
+        let parser = Parser::new().in_zone(chrono_tz::Europe::Berlin);
+        let utc = |s: &str| DateTime::parse_from_rfc3339(s).unwrap();
+        assert_eq!(
+            parser.timestamp("2026.10.25-02.30.00:000"),
+            Some(utc("2026-10-25T00:30:00Z"))
+        );
+        assert_eq!(
+            parser.timestamp("2026.10.25-02.59.00:000"),
+            Some(utc("2026-10-25T00:59:00Z"))
+        );
+        // Back to 02:10, so this is the second pass.
+        assert_eq!(
+            parser.timestamp("2026.10.25-02.10.00:000"),
+            Some(utc("2026-10-25T01:10:00Z"))
+        );
+        assert_eq!(
+            parser.timestamp("2026.10.25-02.40.00:000"),
+            Some(utc("2026-10-25T01:40:00Z"))
+        );
+        assert_eq!(
+            parser.timestamp("2026.10.25-03.00.00:000"),
+            Some(utc("2026-10-25T02:00:00Z"))
+        );
+
+        // A fresh parser starts on the first pass.
+        let parser = parser.in_zone(chrono_tz::Europe::Berlin);
+        assert_eq!(
+            parser.timestamp("2026.10.25-02.10.00:000"),
+            Some(utc("2026-10-25T00:10:00Z"))
+        );
+    }
+
+    #[test]
+    fn rejects_times_in_a_dst_gap() {
+        // 02:30 never happens in Berlin on 2026-03-29.
+        let parser = Parser::new().in_zone(chrono_tz::Europe::Berlin);
+        assert!(parser.timestamp("2026.03.29-02.30.00:000").is_none());
+    }
+}
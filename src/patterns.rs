@@ -0,0 +1,360 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use inputs::HEADER_LINES;
+use parse::Parser;
+use reader::LogReader;
+
+// The patterns that ship with squadlog. Admins can replace them with their
+// own file in the same format when an update changes the log lines.
+const BUILTIN_PATTERNS: &str = include_str!("patterns.json");
+
+// The version of the pattern file format this build understands.
+pub const PATTERN_FILE_VERSION: u32 = 1;
+
+pub fn invalid_patterns(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// What a pattern recognizes, and so which event it produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternKind {
+    Revived,
+    Damaged,
+    PlayerLoggedIn,
+    RoleChanged,
+    Wounded,
+    Died,
+    ControllerStateChanged,
+    MatchStateChanged,
+    MapLoading,
+    PlayerJoined,
+}
+
+impl PatternKind {
+    // The fields a pattern of this kind has to capture.
+    pub fn fields(self) -> &'static [&'static str] {
+        match self {
+            PatternKind::Revived => &["reviver", "revivee"],
+            PatternKind::Damaged | PatternKind::Wounded | PatternKind::Died => {
+                &["victim", "damage", "attacker", "weapon"]
+            }
+            // Logs from before v5 don't print online IDs, so online_ids is
+            // optional.
+            PatternKind::PlayerLoggedIn => &["controller"],
+            PatternKind::RoleChanged => &["player", "role"],
+            PatternKind::ControllerStateChanged => &["controller", "old_state", "new_state"],
+            PatternKind::MatchStateChanged => &["from", "to"],
+            PatternKind::MapLoading => &["map"],
+            PatternKind::PlayerJoined => &["name"],
+        }
+    }
+}
+
+// Splits every line into its timestamp, category and message.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LineDef {
+    pub regex: String,
+    // Field name to capture group number.
+    pub fields: HashMap<String, usize>,
+}
+
+// A pattern run against the message of lines in category.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PatternDef {
+    pub kind: PatternKind,
+    pub category: String,
+    pub regex: String,
+    pub fields: HashMap<String, usize>,
+}
+
+// The patterns for one generation of the log format, used for the game builds
+// from min_build up to, but not including, max_build. Either end can be left
+// open. Logs that don't print their build are matched by detect instead.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PatternSetDef {
+    pub name: String,
+    #[serde(default)]
+    pub min_build: Option<String>,
+    #[serde(default)]
+    pub max_build: Option<String>,
+    // A log whose header has a line matching this is of this generation.
+    #[serde(default)]
+    pub detect: Option<String>,
+    pub line: LineDef,
+    pub patterns: Vec<PatternDef>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PatternFile {
+    pub version: u32,
+    // Finds the game build in a log header. Group 1 is the build.
+    pub build: String,
+    // The set to use for logs whose build we can't find, or that no set
+    // covers.
+    pub default: String,
+    pub sets: Vec<PatternSetDef>,
+}
+
+impl PatternFile {
+    pub fn builtin() -> PatternFile {
+        serde_json::from_str(BUILTIN_PATTERNS).expect("built-in patterns are invalid")
+    }
+
+    pub fn from_file(path: &Path) -> io::Result<PatternFile> {
+        let contents = fs::read_to_string(path)?;
+        let file: PatternFile = serde_json::from_str(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if file.version != PATTERN_FILE_VERSION {
+            return Err(invalid_patterns(format!(
+                "pattern file is version {}, but we only understand version {}",
+                file.version, PATTERN_FILE_VERSION
+            )));
+        }
+        Ok(file)
+    }
+
+    pub fn default_set(&self) -> Option<&PatternSetDef> {
+        self.sets.iter().find(|s| s.name == self.default)
+    }
+}
+
+// Builds are dotted numbers like 7.4.0.460412, compared part by part.
+fn build_parts(build: &str) -> Vec<u64> {
+    build.split('.').map(|p| p.parse().unwrap_or(0)).collect()
+}
+
+struct CompiledSet {
+    min_build: Option<Vec<u64>>,
+    max_build: Option<Vec<u64>>,
+    detect: Option<Regex>,
+    parser: Parser,
+}
+
+impl CompiledSet {
+    fn covers(&self, build: &[u64]) -> bool {
+        let after_min = match &self.min_build {
+            Some(min) => build.cmp(min) != Ordering::Less,
+            None => true,
+        };
+        let before_max = match &self.max_build {
+            Some(max) => build.cmp(max) == Ordering::Less,
+            None => true,
+        };
+        after_min && before_max
+    }
+}
+
+// Every pattern set in a pattern file, compiled and ready to pick from by
+// game build.
+pub struct Patterns {
+    build: Regex,
+    sets: Vec<CompiledSet>,
+    default: usize,
+}
+
+impl Patterns {
+    pub fn compile(file: &PatternFile) -> io::Result<Patterns> {
+        let build = Regex::new(&file.build)
+            .map_err(|e| invalid_patterns(format!("{}: {}", file.build, e)))?;
+        let mut sets = Vec::new();
+        for set in &file.sets {
+            let detect = match &set.detect {
+                Some(d) => {
+                    Some(Regex::new(d).map_err(|e| invalid_patterns(format!("{}: {}", d, e)))?)
+                }
+                None => None,
+            };
+            sets.push(CompiledSet {
+                min_build: set.min_build.as_ref().map(|b| build_parts(b)),
+                max_build: set.max_build.as_ref().map(|b| build_parts(b)),
+                detect,
+                parser: Parser::from_set(set)?,
+            });
+        }
+        let default = match file.sets.iter().position(|s| s.name == file.default) {
+            Some(i) => i,
+            None => {
+                return Err(invalid_patterns(format!(
+                    "the default pattern set {} isn't in the file",
+                    file.default
+                )))
+            }
+        };
+        Ok(Patterns {
+            build,
+            sets,
+            default,
+        })
+    }
+
+    pub fn builtin() -> Patterns {
+        Patterns::compile(&PatternFile::builtin()).expect("built-in patterns are invalid")
+    }
+
+    pub fn from_file(path: &Path) -> io::Result<Patterns> {
+        Patterns::compile(&PatternFile::from_file(path)?)
+    }
+
+    pub fn default_parser(&self) -> &Parser {
+        &self.sets[self.default].parser
+    }
+
+    // The parser for logs from build. The first set that covers the build
+    // wins, so list narrow ranges before wide ones.
+    pub fn parser_for(&self, build: Option<&str>) -> &Parser {
+        let build = match build {
+            Some(b) => build_parts(b),
+            None => return self.default_parser(),
+        };
+        match self.sets.iter().find(|s| s.covers(&build)) {
+            Some(s) => &s.parser,
+            None => self.default_parser(),
+        }
+    }
+
+    // The game build in the header of the log at path, if we can find it.
+    pub fn build_of(&self, path: &Path) -> io::Result<Option<String>> {
+        Ok(self.sniff(path)?.0)
+    }
+
+    // Read the header of the log at path once, for its build and for which
+    // sets' detect patterns it matches.
+    fn sniff(&self, path: &Path) -> io::Result<(Option<String>, Vec<bool>)> {
+        let mut build = None;
+        let mut detected = vec![false; self.sets.len()];
+        let mut reader = LogReader::open(path)?;
+        for _i in 0..HEADER_LINES {
+            let line = match reader.read_log_line()? {
+                Some(line) => line,
+                None => break,
+            };
+            if build.is_none() {
+                if let Some(c) = self.build.captures(&line.text) {
+                    build = c.get(1).map(|m| String::from(m.as_str()));
+                }
+            }
+            for (set, hit) in self.sets.iter().zip(detected.iter_mut()) {
+                if let Some(detect) = &set.detect {
+                    *hit = *hit || detect.is_match(&line.text);
+                }
+            }
+        }
+        Ok((build, detected))
+    }
+
+    // The parser for the format generation of the log at path. Its build
+    // decides if it has one that a set covers; otherwise the first set whose
+    // detect pattern matches its header, and failing that the default.
+    pub fn parser_for_file(&self, path: &Path) -> io::Result<&Parser> {
+        let (build, detected) = self.sniff(path)?;
+        if let Some(build) = build {
+            let build = build_parts(&build);
+            if let Some(s) = self.sets.iter().find(|s| s.covers(&build)) {
+                return Ok(&s.parser);
+            }
+        }
+        match detected.iter().position(|hit| *hit) {
+            Some(i) => Ok(&self.sets[i].parser),
+            None => Ok(self.default_parser()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use state::{apply_line, GameState};
+    use tempfile::tempdir;
+
+    // A game on a 4.x server. Nothing prints online IDs, and wound and die
+    // lines name the attacker by their controller.
+    const PRE_V5: &str = "\
+[2020.04.12-13.39.50:000][  0]LogConfig: Set ProjectVersion to V4.3.1.193341. Version Checksum will be recalculated on next use.
+[2020.04.12-13.40.00:000][100]LogWorld: StartLoadingDestination to: /Game/Maps/Narva/Gameplay_Layers/Narva_AAS_v1
+[2020.04.12-13.41.00:000][200]LogSquad: PostLogin: NewPlayer: BP_PlayerController_C /Game/Maps/Narva/Gameplay_Layers/Narva_AAS_v1.Narva_AAS_v1:PersistentLevel.BP_PlayerController_C_2130401015
+[2020.04.12-13.41.00:100][201]LogNet: Join succeeded: Alice
+[2020.04.12-13.41.05:000][300]LogSquad: PostLogin: NewPlayer: BP_PlayerController_C /Game/Maps/Narva/Gameplay_Layers/Narva_AAS_v1.Narva_AAS_v1:PersistentLevel.BP_PlayerController_C_2130401016
+[2020.04.12-13.41.05:100][301]LogNet: Join succeeded: Bob
+[2020.04.12-13.42.00:000][400]LogSquadTrace: [DedicatedServer]ASQPlayerController::ChangeState(): PC=Alice OldState=Inactive NewState=Playing
+[2020.04.12-13.42.00:000][400]LogSquadTrace: [DedicatedServer]ASQPlayerController::ChangeState(): PC=Bob OldState=Inactive NewState=Playing
+[2020.04.12-13.42.01:000][410]LogSquadTrace: [DedicatedServer]ASQPlayerController::SetCurrentRole(): On Server PC=Alice NewRole=USA_Rifleman_01
+[2020.04.12-13.42.01:000][410]LogSquadTrace: [DedicatedServer]ASQPlayerController::SetCurrentRole(): On Server PC=Bob NewRole=USA_Medic_01
+[2020.04.12-13.45.00:000][500]LogSquadTrace: [DedicatedServer]ASQSoldier::Wound(): Player:Bob KillingDamage=-150.000000 from BP_PlayerController_C_2130401015 caused by BP_M4_M68_C_2130405024
+[2020.04.12-13.45.30:000][600]LogSquadTrace: [DedicatedServer]ASQSoldier::Die(): Player:Bob KillingDamage=150.000000 from BP_PlayerController_C_2130401015 caused by BP_M4_M68_C_2130405024
+";
+
+    // The same game on a 5.x server, which prints online IDs after names and
+    // controllers.
+    const V5: &str = "\
+[2023.06.01-18.59.50:000][  0]LogConfig: Set ProjectVersion to V5.0.1.398315. Version Checksum will be recalculated on next use.
+[2023.06.01-19.00.00:000][100]LogWorld: StartLoadingDestination to: /Game/Maps/Narva/Gameplay_Layers/Narva_AAS_v1
+[2023.06.01-19.01.00:000][200]LogSquad: PostLogin: NewPlayer: BP_PlayerController_C /Game/Maps/Narva/Gameplay_Layers/Narva_AAS_v1.Narva_AAS_v1:PersistentLevel.BP_PlayerController_C_2147 (IP: 10.0.0.1 | Online IDs: EOS: 0002a10186d9414496bf20d22d3860ba steam: 76561198000000001)
+[2023.06.01-19.01.00:100][201]LogNet: Join succeeded: Alice
+[2023.06.01-19.01.05:000][300]LogSquad: PostLogin: NewPlayer: BP_PlayerController_C /Game/Maps/Narva/Gameplay_Layers/Narva_AAS_v1.Narva_AAS_v1:PersistentLevel.BP_PlayerController_C_2148 (IP: 10.0.0.2 | Online IDs: EOS: 0002b20186d9414496bf20d22d3860bb steam: 76561198000000002)
+[2023.06.01-19.01.05:100][301]LogNet: Join succeeded: Bob
+[2023.06.01-19.02.00:000][400]LogSquadTrace: [DedicatedServer]ASQPlayerController::ChangeState(): PC=Alice OldState=Inactive NewState=Playing
+[2023.06.01-19.02.00:000][400]LogSquadTrace: [DedicatedServer]ASQPlayerController::ChangeState(): PC=Bob OldState=Inactive NewState=Playing
+[2023.06.01-19.02.01:000][410]LogSquadTrace: [DedicatedServer]ASQPlayerController::SetCurrentRole(): On Server PC=Alice NewRole=USA_Rifleman_01
+[2023.06.01-19.02.01:000][410]LogSquadTrace: [DedicatedServer]ASQPlayerController::SetCurrentRole(): On Server PC=Bob NewRole=USA_Medic_01
+[2023.06.01-19.05.00:000][500]LogSquadTrace: [DedicatedServer]ASQSoldier::Wound(): Player:Bob KillingDamage=-150.000000 from BP_PlayerController_C_2147 (Online IDs: EOS: 0002a10186d9414496bf20d22d3860ba steam: 76561198000000001 | Controller ID: BP_PlayerController_C_2147) caused by BP_M4_M68_C_2147405024
+[2023.06.01-19.05.30:000][600]LogSquadTrace: [DedicatedServer]ASQSoldier::Die(): Player:Bob KillingDamage=150.000000 from BP_PlayerController_C_2147 (Online IDs: EOS: 0002a10186d9414496bf20d22d3860ba steam: 76561198000000001 | Controller ID: BP_PlayerController_C_2147) caused by BP_M4_M68_C_2147405024
+";
+
+    // Read log with the parser its header picks, returning the state and the
+    // generation it was read as.
+    fn read(log: &str) -> (GameState, String) {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("SquadGame.log");
+        fs::write(&path, log).unwrap();
+        let patterns = Patterns::builtin();
+        let parser = patterns.parser_for_file(&path).unwrap();
+        let mut g = GameState::new();
+        for line in log.lines() {
+            if let Some(parsed) = parser.parse_line(line).unwrap() {
+                apply_line(&parsed, &mut g).unwrap();
+            }
+        }
+        (g, String::from(parser.generation()))
+    }
+
+    // Alice wounded Bob with weapon, and Bob died.
+    fn check_game(g: &GameState, weapon: &str) {
+        let game = &g.games[0];
+        let bob = &game.players["Bob"];
+        assert_eq!(bob.downs, 1);
+        assert_eq!(bob.deaths, 1);
+        assert_eq!(bob.players_killed_by["Alice"], 1);
+        assert_eq!(game.players["Alice"].weapons[weapon].downs_inflicted, 1);
+    }
+
+    #[test]
+    fn reads_pre_v5_logs() {
+        let (g, generation) = read(PRE_V5);
+        assert_eq!(generation, "pre_v5");
+        check_game(&g, "BP_M4_M68_C_2130405024");
+        assert_eq!(g.controllers["BP_PlayerController_C_2130401015"], "Alice");
+    }
+
+    #[test]
+    fn reads_v5_logs() {
+        let (g, generation) = read(V5);
+        assert_eq!(generation, "v5");
+        check_game(&g, "BP_M4_M68_C_2147405024");
+        assert!(g.identities.identity_of_name("Alice").is_some());
+    }
+
+    #[test]
+    fn detects_newer_logs_without_a_build() {
+        let log: String = V5.lines().skip(1).map(|l| format!("{}\n", l)).collect();
+        let (g, generation) = read(&log);
+        assert_eq!(generation, "v5");
+        check_game(&g, "BP_M4_M68_C_2147405024");
+    }
+}
@@ -0,0 +1,274 @@
+use flate2::read::MultiGzDecoder;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Plain,
+    Gzip,
+    Zstd,
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+// Work out how a file is compressed from its first few bytes, so archived logs
+// are read correctly whatever they happen to be named.
+pub fn detect_compression(path: &Path) -> io::Result<Compression> {
+    let mut magic = Vec::new();
+    File::open(path)?.take(4).read_to_end(&mut magic)?;
+
+    if magic.starts_with(&GZIP_MAGIC) {
+        Ok(Compression::Gzip)
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        Ok(Compression::Zstd)
+    } else {
+        Ok(Compression::Plain)
+    }
+}
+
+// Counts the bytes read from the file itself, before any decompression, so
+// progress can be reported against the size of the file on disk.
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+// A buffered reader over the decompressed contents of a log file.
+pub struct LogReader {
+    inner: Box<dyn BufRead>,
+    raw_read: Rc<Cell<u64>>,
+    compression: Compression,
+    buf: Vec<u8>,
+}
+
+// One line of a log with the line ending taken off.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub text: String,
+    // How many bytes the line took up, including the line ending.
+    pub len: u64,
+    // False if the file ended before we saw a newline.
+    pub complete: bool,
+    // True if the line wasn't valid UTF-8 and had bad bytes replaced.
+    pub lossy: bool,
+}
+
+// Decode a raw line, dropping a trailing \n or \r\n. Invalid UTF-8 is replaced
+// rather than rejected; the returned flag says whether that happened.
+pub fn decode_line(bytes: &[u8]) -> (String, bool) {
+    let mut end = bytes.len();
+    if end > 0 && bytes[end - 1] == b'\n' {
+        end -= 1;
+    }
+    if end > 0 && bytes[end - 1] == b'\r' {
+        end -= 1;
+    }
+
+    match String::from_utf8_lossy(&bytes[..end]) {
+        Cow::Borrowed(s) => (String::from(s), false),
+        Cow::Owned(s) => (s, true),
+    }
+}
+
+impl LogReader {
+    pub fn open(path: &Path) -> io::Result<LogReader> {
+        LogReader::open_at(path, 0)
+    }
+
+    // Open a log and skip the first offset bytes of its decompressed contents.
+    // Plain files seek straight there, compressed ones have to be decompressed
+    // up to that point.
+    pub fn open_at(path: &Path, offset: u64) -> io::Result<LogReader> {
+        let compression = detect_compression(path)?;
+        let mut file = File::open(path)?;
+        if compression == Compression::Plain {
+            file.seek(SeekFrom::Start(offset))?;
+        }
+
+        let raw_read = Rc::new(Cell::new(0));
+        let counted = CountingReader {
+            inner: file,
+            count: raw_read.clone(),
+        };
+        let inner: Box<dyn BufRead> = match compression {
+            Compression::Plain => Box::new(BufReader::new(counted)),
+            Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(counted))),
+            Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::new(counted)?)),
+        };
+
+        let mut reader = LogReader {
+            inner,
+            raw_read,
+            compression,
+            buf: Vec::new(),
+        };
+        if compression != Compression::Plain {
+            io::copy(&mut reader.by_ref().take(offset), &mut io::sink())?;
+        }
+
+        Ok(reader)
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    // Read the next line, or None at the end of the file. Lines are split on
+    // \n only, so a log with \r\n endings reads the same as one without.
+    pub fn read_log_line(&mut self) -> io::Result<Option<LogLine>> {
+        self.buf.clear();
+        let n = self.inner.read_until(b'\n', &mut self.buf)?;
+        if n == 0 {
+            return Ok(None);
+        }
+
+        let (text, lossy) = decode_line(&self.buf);
+        Ok(Some(LogLine {
+            text,
+            len: n as u64,
+            complete: self.buf.last() == Some(&b'\n'),
+            lossy,
+        }))
+    }
+
+    // How many bytes of the file on disk have been read so far. For plain
+    // files opened at an offset this doesn't include the skipped bytes.
+    pub fn raw_bytes_read(&self) -> u64 {
+        self.raw_read.get()
+    }
+}
+
+impl Read for LogReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl BufRead for LogReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    const LOG: &str = "first line\nsecond line\nthird line\n";
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn lines(reader: &mut LogReader) -> Vec<String> {
+        let mut lines = Vec::new();
+        while let Some(line) = reader.read_log_line().unwrap() {
+            lines.push(line.text);
+        }
+        lines
+    }
+
+    #[test]
+    fn detects_compression_by_magic_not_name() {
+        let dir = tempdir().unwrap();
+        let gz = dir.path().join("SquadGame.log");
+        let zst = dir.path().join("SquadGame.log.gz");
+        let plain = dir.path().join("SquadGame.log.zst");
+        fs::write(&gz, gzip(LOG.as_bytes())).unwrap();
+        fs::write(&zst, zstd::encode_all(LOG.as_bytes(), 0).unwrap()).unwrap();
+        fs::write(&plain, LOG).unwrap();
+
+        assert_eq!(detect_compression(&gz).unwrap(), Compression::Gzip);
+        assert_eq!(detect_compression(&zst).unwrap(), Compression::Zstd);
+        assert_eq!(detect_compression(&plain).unwrap(), Compression::Plain);
+        for path in &[&gz, &zst, &plain] {
+            assert_eq!(
+                lines(&mut LogReader::open(path).unwrap()),
+                vec!["first line", "second line", "third line"]
+            );
+        }
+    }
+
+    #[test]
+    fn resumes_partway_through_a_compressed_log() {
+        let dir = tempdir().unwrap();
+        let gz = dir.path().join("a.log.gz");
+        let zst = dir.path().join("a.log.zst");
+        // Concatenated gzip files read as one log, so split this one in two.
+        let mut members = gzip(b"first line\n");
+        members.extend(gzip(b"second line\nthird line\n"));
+        fs::write(&gz, members).unwrap();
+        fs::write(&zst, zstd::encode_all(LOG.as_bytes(), 0).unwrap()).unwrap();
+
+        let offset = "first line\nsecond line\n".len() as u64;
+        for path in &[&gz, &zst] {
+            let mut reader = LogReader::open_at(path, offset).unwrap();
+            assert_eq!(lines(&mut reader), vec!["third line"]);
+            assert_eq!(reader.raw_bytes_read(), fs::metadata(path).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn strips_crlf_and_keeps_its_length() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("SquadGame.log");
+        fs::write(&path, "first line\r\nsecond line\r\nhalf a li").unwrap();
+
+        let mut reader = LogReader::open(&path).unwrap();
+        let first = reader.read_log_line().unwrap().unwrap();
+        assert_eq!(first.text, "first line");
+        assert_eq!(first.len, "first line\r\n".len() as u64);
+        assert!(first.complete);
+        let second = reader.read_log_line().unwrap().unwrap();
+        assert_eq!(second.text, "second line");
+        let last = reader.read_log_line().unwrap().unwrap();
+        assert_eq!(last.text, "half a li");
+        assert!(!last.complete);
+        assert!(reader.read_log_line().unwrap().is_none());
+    }
+
+    #[test]
+    fn replaces_invalid_utf8_and_says_so() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("SquadGame.log");
+        fs::write(&path, b"Player:B\xf6b\r\nfine\n\xff\xfe\n").unwrap();
+
+        let mut reader = LogReader::open(&path).unwrap();
+        let mut read = Vec::new();
+        while let Some(line) = reader.read_log_line().unwrap() {
+            read.push((line.text, line.lossy));
+        }
+        assert_eq!(
+            read,
+            vec![
+                (String::from("Player:B\u{fffd}b"), true),
+                (String::from("fine"), false),
+                (String::from("\u{fffd}\u{fffd}"), true),
+            ]
+        );
+        assert_eq!(read.iter().filter(|(_text, lossy)| *lossy).count(), 2);
+    }
+}
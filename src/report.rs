@@ -0,0 +1,153 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use state::{display_time, effective_identity, weapons_by_name, Game, GameState, Player};
+
+// One player's line on a match scoreboard.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScoreboardRow {
+    pub name: String,
+    pub identity: Option<String>,
+    // Other players this player put down.
+    pub downs_inflicted: u32,
+    // Times this player went down.
+    pub downs_suffered: u32,
+    // Times this player died, bled out or gave up.
+    pub deaths: u32,
+    pub revives: u32,
+    pub damage_dealt: f32,
+    pub classes: Vec<String>,
+}
+
+// A summary of one match, written out when it ends. Times are in the game's
+// display zone.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MatchReport {
+    pub map: String,
+    pub start_time: DateTime<FixedOffset>,
+    pub end_time: Option<DateTime<FixedOffset>>,
+    pub duration_seconds: Option<i64>,
+    pub player_count: usize,
+    // Most downs first.
+    pub players: Vec<ScoreboardRow>,
+}
+
+fn damage_dealt(p: &Player) -> f32 {
+    weapons_by_name(&p.weapons)
+        .into_iter()
+        .map(|(_, w)| w.damage_dealt)
+        .sum()
+}
+
+pub fn match_report(g: &GameState, game: &Game) -> MatchReport {
+    let mut players: Vec<ScoreboardRow> = game
+        .players
+        .values()
+        .map(|p| {
+            let mut classes: Vec<String> = p.classes_played.iter().cloned().collect();
+            classes.sort();
+            ScoreboardRow {
+                name: p.name.clone(),
+                identity: effective_identity(g, game, p),
+                downs_inflicted: p.players_killed.values().sum(),
+                downs_suffered: p.downs,
+                deaths: p.deaths,
+                revives: p.players_revived.values().sum(),
+                damage_dealt: damage_dealt(p),
+                classes,
+            }
+        })
+        .collect();
+    players.sort_by(|a, b| {
+        b.downs_inflicted
+            .cmp(&a.downs_inflicted)
+            .then(a.downs_suffered.cmp(&b.downs_suffered))
+            .then(a.name.cmp(&b.name))
+    });
+
+    MatchReport {
+        map: game.map.clone(),
+        start_time: display_time(game, &game.start_time),
+        end_time: game.end_time.map(|t| display_time(game, &t)),
+        duration_seconds: game
+            .end_time
+            .map(|t| t.signed_duration_since(game.start_time).num_seconds()),
+        player_count: game.players.len(),
+        players,
+    }
+}
+
+// The layer name from a map path like Narva/Gameplay_Layers/Narva_AAS_v1.
+pub fn layer_name(map: &str) -> &str {
+    let last = map.rsplit('/').next().unwrap_or(map);
+    last.rsplit('.').next().unwrap_or(last)
+}
+
+// Pipes would end a Markdown table cell early.
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+pub fn format_duration(seconds: i64) -> String {
+    format!("{}m {:02}s", seconds / 60, seconds % 60)
+}
+
+impl MatchReport {
+    // What the report's files are called, without the extension: the layer
+    // and when the match started, which sort by map and then by time.
+    pub fn file_stem(&self) -> String {
+        let layer: String = layer_name(&self.map)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("{}_{}", layer, self.start_time.format("%Y-%m-%dT%H%M%S"))
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("# {}\n\n", cell(layer_name(&self.map))));
+        out.push_str(&format!("- Map: {}\n", self.map));
+        out.push_str(&format!(
+            "- Started: {}\n",
+            self.start_time.format("%Y-%m-%d %H:%M:%S %:z")
+        ));
+        if let (Some(end), Some(duration)) = (self.end_time, self.duration_seconds) {
+            out.push_str(&format!(
+                "- Ended: {} ({})\n",
+                end.format("%Y-%m-%d %H:%M:%S %:z"),
+                format_duration(duration)
+            ));
+        }
+        out.push_str(&format!("- Players: {}\n\n", self.player_count));
+
+        out.push_str("| Player | Downs | Downed | Deaths | Revives | Damage | Classes |\n");
+        out.push_str("|---|---:|---:|---:|---:|---:|---|\n");
+        for p in &self.players {
+            out.push_str(&format!(
+                "| {} | {} | {} | {} | {} | {:.0} | {} |\n",
+                cell(&p.name),
+                p.downs_inflicted,
+                p.downs_suffered,
+                p.deaths,
+                p.revives,
+                p.damage_dealt,
+                cell(&p.classes.join(", "))
+            ));
+        }
+        out
+    }
+}
+
+// Write report into dir as JSON and Markdown, returning both paths.
+pub fn write_match_report(dir: &Path, report: &MatchReport) -> io::Result<(PathBuf, PathBuf)> {
+    fs::create_dir_all(dir)?;
+    let stem = report.file_stem();
+    let json = dir.join(format!("{}.json", stem));
+    let markdown = dir.join(format!("{}.md", stem));
+    fs::write(&json, serde_json::to_string_pretty(report)?)?;
+    fs::write(&markdown, report.to_markdown())?;
+    Ok((json, markdown))
+}
@@ -0,0 +1,231 @@
+use serde_json::{Map, Value};
+use std::io;
+
+use state::GameState;
+
+// The statefile version this build writes. Whenever a change to GameState or
+// anything in it would stop older statefiles loading, or make them mean
+// something else, bump it and add a migration from the old version.
+pub const STATE_VERSION: u32 = 2;
+
+// Upgrades a statefile from version from to from + 1. Statefiles from before
+// we kept a version are version 0.
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    // Returns a note for each kind of change it made.
+    pub apply: fn(&mut Map<String, Value>) -> Vec<String>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "statefiles from before they had a version",
+        apply: from_unversioned,
+    },
+    Migration {
+        from: 1,
+        description: "statefiles from before the event log",
+        apply: add_event_log,
+    },
+];
+
+fn invalid_state(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn is_missing(object: &Map<String, Value>, key: &str) -> bool {
+    object.get(key).is_none_or(Value::is_null)
+}
+
+// Games from before zones were recorded were read as UTC. Players from before
+// downs were counted get them back from who downed them, which misses downs
+// we never found the attacker for.
+fn from_unversioned(state: &mut Map<String, Value>) -> Vec<String> {
+    let mut zoned = 0;
+    let mut downs = 0;
+    let games = state.get_mut("games").and_then(Value::as_array_mut);
+    for game in games.into_iter().flatten() {
+        let game = match game.as_object_mut() {
+            Some(game) => game,
+            None => continue,
+        };
+        if is_missing(game, "source_timezone") || is_missing(game, "display_timezone") {
+            for key in &["source_timezone", "display_timezone"] {
+                if is_missing(game, key) {
+                    game.insert(String::from(*key), Value::from("UTC"));
+                }
+            }
+            zoned += 1;
+        }
+        let players = game.get_mut("players").and_then(Value::as_object_mut);
+        for player in players.into_iter().flat_map(|p| p.values_mut()) {
+            let player = match player.as_object_mut() {
+                Some(player) => player,
+                None => continue,
+            };
+            if !is_missing(player, "downs") {
+                continue;
+            }
+            let killed_by: u64 = player
+                .get("players_killed_by")
+                .and_then(Value::as_object)
+                .map_or(0, |k| k.values().filter_map(Value::as_u64).sum());
+            player.insert(String::from("downs"), Value::from(killed_by));
+            downs += 1;
+        }
+    }
+
+    let mut notes = Vec::new();
+    if zoned > 0 {
+        notes.push(format!("marked {} games as logged and shown in UTC", zoned));
+    }
+    if downs > 0 {
+        notes.push(format!(
+            "counted downs for {} players from who downed them",
+            downs
+        ));
+    }
+    notes
+}
+
+// The event log starts empty. It only covers the whole history of statefiles
+// that have no games yet.
+fn add_event_log(state: &mut Map<String, Value>) -> Vec<String> {
+    let empty = state
+        .get("games")
+        .and_then(Value::as_array)
+        .is_none_or(|games| games.is_empty());
+    state.insert(String::from("events_len"), Value::from(0));
+    state.insert(String::from("events_complete"), Value::from(empty));
+    if empty {
+        Vec::new()
+    } else {
+        vec![String::from(
+            "the event log will only cover logs ingested from now on, so rebuild won't be possible",
+        )]
+    }
+}
+
+// The version of a statefile, as JSON.
+pub fn state_version(state: &Value) -> u32 {
+    state.get("version").and_then(Value::as_u64).unwrap_or(0) as u32
+}
+
+// Bring a statefile up to STATE_VERSION in place. Returns each migration that
+// ran, with its notes.
+pub fn migrate(state: &mut Value) -> io::Result<Vec<(&'static Migration, Vec<String>)>> {
+    let mut version = state_version(state);
+    if version > STATE_VERSION {
+        return Err(invalid_state(format!(
+            "statefile is version {}, but we only understand up to version {}",
+            version, STATE_VERSION
+        )));
+    }
+    let object = match state.as_object_mut() {
+        Some(object) => object,
+        None => return Err(invalid_state(String::from("statefile isn't a JSON object"))),
+    };
+
+    let mut applied = Vec::new();
+    while version < STATE_VERSION {
+        let m = match MIGRATIONS.iter().find(|m| m.from == version) {
+            Some(m) => m,
+            None => {
+                return Err(invalid_state(format!(
+                    "no migration from statefile version {}",
+                    version
+                )))
+            }
+        };
+        let notes = (m.apply)(object);
+        version += 1;
+        object.insert(String::from("version"), Value::from(version));
+        applied.push((m, notes));
+    }
+    Ok(applied)
+}
+
+// Parse a statefile, migrating it first if it's from an older squadlog.
+pub fn load_state(contents: &str) -> io::Result<GameState> {
+    let mut state: Value =
+        serde_json::from_str(contents).map_err(|e| invalid_state(e.to_string()))?;
+    migrate(&mut state)?;
+    serde_json::from_value(state).map_err(|e| invalid_state(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A statefile as squadlog wrote them before they had a version.
+    fn unversioned() -> Value {
+        serde_json::json!({
+            "games": [{
+                "map": "Narva",
+                "start_time": "2021-06-01T10:00:00+00:00",
+                "players": {
+                    "Bob": {
+                        "name": "Bob",
+                        "state": "Playing",
+                        "hitpoints": 100.0,
+                        "last_damaged": null,
+                        "last_spawn_time": null,
+                        "last_down_time": null,
+                        "players_killed_by": { "Alice": 2, "Carl": 1 },
+                        "players_killed": {},
+                        "classes_played": ["USA_Rifleman_01"],
+                        "players_revived_by": {},
+                        "players_revived": {}
+                    }
+                }
+            }],
+            "current_game_start_time": "2021-06-01T10:00:00+00:00",
+            "last_timestamp": "2021-06-01T10:30:00+00:00",
+            "player_names": []
+        })
+    }
+
+    #[test]
+    fn migrates_an_unversioned_statefile() {
+        let mut state = unversioned();
+        assert_eq!(state_version(&state), 0);
+        let applied = migrate(&mut state).unwrap();
+        let froms: Vec<u32> = applied.iter().map(|(m, _notes)| m.from).collect();
+        assert_eq!(froms, vec![0, 1]);
+        assert_eq!(state_version(&state), STATE_VERSION);
+
+        let g: GameState = serde_json::from_value(state).unwrap();
+        let game = &g.games[0];
+        assert_eq!(game.source_timezone, Some(chrono_tz::UTC));
+        assert_eq!(game.display_timezone, Some(chrono_tz::UTC));
+        assert_eq!(game.players["Bob"].downs, 3);
+        assert_eq!(g.events_len, 0);
+        assert!(!g.events_complete);
+    }
+
+    #[test]
+    fn an_empty_statefile_keeps_a_complete_event_log() {
+        let mut state = unversioned();
+        state["games"] = serde_json::json!([]);
+        let applied = migrate(&mut state).unwrap();
+        assert!(applied.iter().all(|(_m, notes)| notes.is_empty()));
+        assert_eq!(state["events_complete"], Value::from(true));
+    }
+
+    #[test]
+    fn a_current_statefile_is_left_alone() {
+        let mut state = serde_json::to_value(GameState::new()).unwrap();
+        let before = state.clone();
+        assert!(migrate(&mut state).unwrap().is_empty());
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn refuses_statefiles_from_a_newer_squadlog() {
+        let mut state = unversioned();
+        state["version"] = Value::from(STATE_VERSION + 1);
+        assert!(migrate(&mut state).is_err());
+        assert!(load_state(&state.to_string()).is_err());
+    }
+}
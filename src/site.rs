@@ -0,0 +1,620 @@
+use chrono::{DateTime, FixedOffset};
+use minijinja::{context, Environment};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use report::{format_duration, layer_name, match_report};
+use state::GameState;
+use stats::{lifetime_stats, player_key, player_labels, PlayerOutput};
+use timezones::in_zone;
+use weapons::{WeaponCatalog, WeaponCategory};
+
+const SITE_TITLE: &str = "Squad stats";
+
+// The templates the site is rendered with. A directory of templates can
+// replace any of them by name.
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("layout.html", include_str!("templates/layout.html")),
+    (
+        "leaderboard.html",
+        include_str!("templates/leaderboard.html"),
+    ),
+    ("player.html", include_str!("templates/player.html")),
+    ("match.html", include_str!("templates/match.html")),
+    ("matches.html", include_str!("templates/matches.html")),
+    ("map.html", include_str!("templates/map.html")),
+    ("maps.html", include_str!("templates/maps.html")),
+];
+
+// The ways the leaderboard can be sorted, each its own page so the site works
+// without any JavaScript.
+const LEADERBOARDS: &[(&str, &str)] = &[
+    ("kills", "Kills"),
+    ("deaths", "Deaths"),
+    ("kd", "K/D"),
+    ("revives", "Revives"),
+];
+
+fn template_error(e: minijinja::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+// The built-in templates, with any .html file in dir added or replacing the
+// built-in one of the same name.
+pub fn site_templates(dir: Option<&Path>) -> io::Result<Environment<'static>> {
+    let mut env = Environment::new();
+    for (name, source) in BUILTIN_TEMPLATES {
+        env.add_template(name, source).map_err(template_error)?;
+    }
+    if let Some(dir) = dir {
+        let mut paths: Vec<_> = fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<_>>()?;
+        paths.sort();
+        for path in paths {
+            if path.extension().is_none_or(|ext| ext != "html") {
+                continue;
+            }
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let source = fs::read_to_string(&path)?;
+            env.add_template_owned(name, source).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            })?;
+        }
+    }
+    Ok(env)
+}
+
+// Hands out file names that are safe on any filesystem and never repeat.
+#[derive(Default)]
+struct Slugs {
+    used: HashSet<String>,
+}
+
+impl Slugs {
+    fn slug(&mut self, s: &str) -> String {
+        let base: String = s
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let mut slug = base.clone();
+        let mut n = 1;
+        while !self.used.insert(slug.clone()) {
+            n += 1;
+            slug = format!("{}-{}", base, n);
+        }
+        slug
+    }
+}
+
+fn format_time(t: &DateTime<FixedOffset>) -> String {
+    t.format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn kd(kills: u32, deaths: u32) -> f64 {
+    f64::from(kills) / f64::from(deaths.max(1))
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct LeaderboardRow {
+    name: String,
+    slug: String,
+    kills: u32,
+    deaths: u32,
+    kd: String,
+    revives: u32,
+    games: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Column {
+    title: &'static str,
+    page: String,
+    sorted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PlayerSummary {
+    name: String,
+    identity: Option<String>,
+    kills: u32,
+    deaths: u32,
+    kd: String,
+    revives: u32,
+    revived: u32,
+    downs: u32,
+    median_time_to_revive: String,
+    revive_rate_per_down: String,
+    classes: Vec<String>,
+    aliases: Vec<AliasRow>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct AliasRow {
+    name: String,
+    first_seen: String,
+    last_seen: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct OpponentRow {
+    name: String,
+    slug: Option<String>,
+    kills: u32,
+    killed_by: u32,
+    revives: u32,
+    revived_by: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct WeaponRow {
+    name: String,
+    category: WeaponCategory,
+    damage_dealt: String,
+    downs_inflicted: u32,
+    downs_suffered: u32,
+}
+
+// One match, as a line in a list of them.
+#[derive(Debug, Clone, Serialize)]
+struct MatchRow {
+    slug: String,
+    map: String,
+    map_slug: String,
+    layer: String,
+    start_time: String,
+    end_time: String,
+    duration: String,
+    player_count: usize,
+}
+
+// One player's line on a match scoreboard, linked to their profile.
+#[derive(Debug, Clone, Serialize)]
+struct ScoreRow {
+    name: String,
+    slug: String,
+    downs_inflicted: u32,
+    downs_suffered: u32,
+    deaths: u32,
+    revives: u32,
+    damage_dealt: String,
+    classes: Vec<String>,
+}
+
+// A match, as a line on a player's profile.
+#[derive(Debug, Clone, Serialize)]
+struct PlayerMatch {
+    slug: String,
+    layer: String,
+    start_time: String,
+    played_as: String,
+    downs_inflicted: u32,
+    downs_suffered: u32,
+    deaths: u32,
+    revives: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct MapPlayer {
+    name: String,
+    slug: String,
+    downs_inflicted: u32,
+    downs_suffered: u32,
+    deaths: u32,
+    revives: u32,
+    matches: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct MapPage {
+    map: String,
+    slug: String,
+    layer: String,
+    last_played: String,
+    // Most recent first.
+    matches: Vec<MatchRow>,
+    // Most downs first.
+    players: Vec<MapPlayer>,
+}
+
+fn player_summary(p: &PlayerOutput) -> PlayerSummary {
+    let mut classes: Vec<String> = p.classes.iter().cloned().collect();
+    classes.sort();
+    PlayerSummary {
+        name: p.name.clone(),
+        identity: p.identity.clone(),
+        kills: p.count_kills,
+        deaths: p.count_killed,
+        kd: format!("{:.2}", kd(p.count_kills, p.count_killed)),
+        revives: p.count_revives,
+        revived: p.count_revived,
+        downs: p.count_downs,
+        median_time_to_revive: match p.median_time_to_revive {
+            Some(t) => format!("{:.1}s", t),
+            None => String::from("-"),
+        },
+        revive_rate_per_down: match p.revive_rate_per_down {
+            Some(r) => format!("{:.0}%", r * 100.0),
+            None => String::from("-"),
+        },
+        classes,
+        aliases: p
+            .aliases
+            .iter()
+            .map(|a| AliasRow {
+                name: a.name.clone(),
+                first_seen: format_time(&a.first_seen),
+                last_seen: format_time(&a.last_seen),
+            })
+            .collect(),
+    }
+}
+
+fn opponents(p: &PlayerOutput, slugs: &HashMap<String, String>) -> Vec<OpponentRow> {
+    let count = |m: &HashMap<String, u32>, name: &String| m.get(name).cloned().unwrap_or(0);
+    let names: BTreeSet<&String> = p
+        .kills
+        .keys()
+        .chain(p.killed_by.keys())
+        .chain(p.revives.keys())
+        .chain(p.revived_by.keys())
+        .collect();
+    names
+        .into_iter()
+        .map(|name| OpponentRow {
+            name: name.clone(),
+            slug: slugs.get(name).cloned(),
+            kills: count(&p.kills, name),
+            killed_by: count(&p.killed_by, name),
+            revives: count(&p.revives, name),
+            revived_by: count(&p.revived_by, name),
+        })
+        .collect()
+}
+
+fn render<S: Serialize>(env: &Environment, template: &str, path: &Path, ctx: S) -> io::Result<()> {
+    let html = env
+        .get_template(template)
+        .and_then(|t| t.render(ctx))
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("rendering {}: {}", template, e),
+            )
+        })?;
+    fs::write(path, html)
+}
+
+// Render g into dir as a static site: the leaderboard, sorted each way, and a
+// page for every player, match and map. Pages only link to each other, so the
+// site can be served from anywhere or opened straight from disk. Returns how
+// many pages were written.
+pub fn write_site(
+    g: &GameState,
+    catalog: &WeaponCatalog,
+    env: &Environment,
+    dir: &Path,
+) -> io::Result<usize> {
+    for sub in &["players", "matches", "maps"] {
+        fs::create_dir_all(dir.join(sub))?;
+    }
+    let updated = format_time(&in_zone(&g.last_timestamp, g.timezones.display_zone()));
+    let mut pages = 0;
+
+    let lifetime = lifetime_stats(g, catalog);
+    let labels = player_labels(g);
+    let mut names: Vec<&String> = lifetime.keys().collect();
+    names.sort();
+    let mut slugs = Slugs::default();
+    let player_slugs: HashMap<String, String> = names
+        .iter()
+        .map(|name| ((*name).clone(), slugs.slug(name)))
+        .collect();
+
+    let mut player_matches: HashMap<String, Vec<PlayerMatch>> = HashMap::new();
+    let mut matches: Vec<MatchRow> = Vec::new();
+    let mut maps: BTreeMap<String, (Vec<MatchRow>, HashMap<String, MapPlayer>)> = BTreeMap::new();
+    let mut match_slugs = Slugs::default();
+    let mut map_slugs: HashMap<String, String> = HashMap::new();
+    let mut used_map_slugs = Slugs::default();
+    for game in &g.games {
+        let report = match_report(g, game);
+        let layer = layer_name(&report.map).to_string();
+        let map_slug = map_slugs
+            .entry(report.map.clone())
+            .or_insert_with(|| used_map_slugs.slug(&layer))
+            .clone();
+        let row = MatchRow {
+            slug: match_slugs.slug(&report.file_stem()),
+            map: report.map.clone(),
+            map_slug,
+            layer: layer.clone(),
+            start_time: format_time(&report.start_time),
+            end_time: report.end_time.as_ref().map_or(String::new(), format_time),
+            duration: report
+                .duration_seconds
+                .map_or(String::from("-"), format_duration),
+            player_count: report.player_count,
+        };
+
+        let (map_matches, map_players) = maps.entry(report.map.clone()).or_default();
+        let mut scoreboard = Vec::new();
+        for p in &report.players {
+            let label = &labels[&player_key(g, game, &game.players[&p.name])];
+            let slug = player_slugs[label].clone();
+            scoreboard.push(ScoreRow {
+                name: label.clone(),
+                slug: slug.clone(),
+                downs_inflicted: p.downs_inflicted,
+                downs_suffered: p.downs_suffered,
+                deaths: p.deaths,
+                revives: p.revives,
+                damage_dealt: format!("{:.0}", p.damage_dealt),
+                classes: p.classes.clone(),
+            });
+            player_matches
+                .entry(label.clone())
+                .or_default()
+                .push(PlayerMatch {
+                    slug: row.slug.clone(),
+                    layer: layer.clone(),
+                    start_time: row.start_time.clone(),
+                    played_as: p.name.clone(),
+                    downs_inflicted: p.downs_inflicted,
+                    downs_suffered: p.downs_suffered,
+                    deaths: p.deaths,
+                    revives: p.revives,
+                });
+            let mp = map_players
+                .entry(label.clone())
+                .or_insert_with(|| MapPlayer {
+                    name: label.clone(),
+                    slug,
+                    ..MapPlayer::default()
+                });
+            mp.downs_inflicted += p.downs_inflicted;
+            mp.downs_suffered += p.downs_suffered;
+            mp.deaths += p.deaths;
+            mp.revives += p.revives;
+            mp.matches += 1;
+        }
+
+        render(
+            env,
+            "match.html",
+            &dir.join("matches").join(format!("{}.html", row.slug)),
+            context! {
+                root => "../",
+                site_title => SITE_TITLE,
+                updated => updated,
+                match => row,
+                players => scoreboard,
+            },
+        )?;
+        pages += 1;
+        map_matches.push(row.clone());
+        matches.push(row);
+    }
+
+    // Players, under every sort order.
+    let mut leaderboard: Vec<LeaderboardRow> = names
+        .iter()
+        .map(|name| {
+            let p = &lifetime[*name];
+            LeaderboardRow {
+                name: p.name.clone(),
+                slug: player_slugs[*name].clone(),
+                kills: p.count_kills,
+                deaths: p.count_killed,
+                kd: format!("{:.2}", kd(p.count_kills, p.count_killed)),
+                revives: p.count_revives,
+                games: player_matches.get(*name).map_or(0, Vec::len),
+            }
+        })
+        .collect();
+    let page_of = |sort: &str| match sort {
+        "kills" => String::from("index.html"),
+        _ => format!("leaderboard-{}.html", sort),
+    };
+    for (sort, _title) in LEADERBOARDS {
+        leaderboard.sort_by(|a, b| {
+            let by = match *sort {
+                "kills" => b.kills.cmp(&a.kills),
+                "deaths" => b.deaths.cmp(&a.deaths),
+                "kd" => kd(b.kills, b.deaths)
+                    .partial_cmp(&kd(a.kills, a.deaths))
+                    .unwrap(),
+                _ => b.revives.cmp(&a.revives),
+            };
+            by.then(a.name.cmp(&b.name))
+        });
+        let columns: Vec<Column> = LEADERBOARDS
+            .iter()
+            .map(|(s, title)| Column {
+                title,
+                page: page_of(s),
+                sorted: s == sort,
+            })
+            .collect();
+        render(
+            env,
+            "leaderboard.html",
+            &dir.join(page_of(sort)),
+            context! {
+                root => "",
+                site_title => SITE_TITLE,
+                updated => updated,
+                sort => sort,
+                columns => columns,
+                players => leaderboard,
+            },
+        )?;
+        pages += 1;
+    }
+
+    for name in &names {
+        let p = &lifetime[*name];
+        let weapons: Vec<WeaponRow> = p
+            .weapons
+            .iter()
+            .map(|w| WeaponRow {
+                name: w.name.clone(),
+                category: w.category,
+                damage_dealt: format!("{:.0}", w.damage_dealt),
+                downs_inflicted: w.downs_inflicted,
+                downs_suffered: w.downs_suffered,
+            })
+            .collect();
+        let mut played: Vec<PlayerMatch> = player_matches.remove(*name).unwrap_or_default();
+        played.reverse();
+        render(
+            env,
+            "player.html",
+            &dir.join("players")
+                .join(format!("{}.html", player_slugs[*name])),
+            context! {
+                root => "../",
+                site_title => SITE_TITLE,
+                updated => updated,
+                player => player_summary(p),
+                opponents => opponents(p, &player_slugs),
+                weapons => weapons,
+                matches => played,
+            },
+        )?;
+        pages += 1;
+    }
+
+    let mut map_pages: Vec<MapPage> = Vec::new();
+    for (map, (mut map_matches, map_players)) in maps {
+        map_matches.reverse();
+        let mut players: Vec<MapPlayer> = map_players.into_values().collect();
+        players.sort_by(|a, b| {
+            b.downs_inflicted
+                .cmp(&a.downs_inflicted)
+                .then(a.downs_suffered.cmp(&b.downs_suffered))
+                .then(a.name.cmp(&b.name))
+        });
+        let page = MapPage {
+            slug: map_slugs[&map].clone(),
+            layer: layer_name(&map).to_string(),
+            last_played: map_matches[0].start_time.clone(),
+            map,
+            matches: map_matches,
+            players,
+        };
+        render(
+            env,
+            "map.html",
+            &dir.join("maps").join(format!("{}.html", page.slug)),
+            context! {
+                root => "../",
+                site_title => SITE_TITLE,
+                updated => updated,
+                map => page,
+            },
+        )?;
+        pages += 1;
+        map_pages.push(page);
+    }
+    map_pages.sort_by(|a, b| a.layer.cmp(&b.layer).then(a.map.cmp(&b.map)));
+
+    // Most recent first.
+    matches.reverse();
+    render(
+        env,
+        "matches.html",
+        &dir.join("matches.html"),
+        context! {
+            root => "",
+            site_title => SITE_TITLE,
+            updated => updated,
+            matches => matches,
+        },
+    )?;
+    render(
+        env,
+        "maps.html",
+        &dir.join("maps.html"),
+        context! {
+            root => "",
+            site_title => SITE_TITLE,
+            updated => updated,
+            maps => map_pages,
+        },
+    )?;
+    Ok(pages + 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event::SquadEvent;
+    use parse::get_dt;
+    use state::apply_event;
+    use tempfile::tempdir;
+
+    const NASTY: &str = "<script>alert(1)</script>";
+
+    #[test]
+    fn escapes_player_names() {
+        let t = get_dt("2026.10.01-10.00.00:000").unwrap();
+        let mut events = vec![SquadEvent::MapLoading {
+            timestamp: t,
+            map: String::from("Narva"),
+        }];
+        for name in &["Alice", NASTY] {
+            events.push(SquadEvent::ControllerStateChanged {
+                timestamp: t,
+                controller: String::from(*name),
+                old_state: String::from("Inactive"),
+                new_state: String::from("Playing"),
+            });
+            events.push(SquadEvent::RoleChanged {
+                timestamp: t,
+                player: String::from(*name),
+                role: String::from("USA_Rifleman_01"),
+            });
+        }
+        events.push(SquadEvent::Wounded {
+            timestamp: t,
+            victim: String::from(NASTY),
+            damage: 100.0,
+            attacker: String::from("Alice"),
+            weapon: String::from("BP_M4_C_1"),
+        });
+        let mut g = GameState::new();
+        for event in &events {
+            apply_event(event, &mut g).unwrap();
+        }
+
+        let dir = tempdir().unwrap();
+        let env = site_templates(None).unwrap();
+        write_site(&g, &WeaponCatalog::builtin(), &env, dir.path()).unwrap();
+
+        let index = fs::read_to_string(dir.path().join("index.html")).unwrap();
+        assert!(index.contains("&lt;script&gt;alert(1)"));
+        for sub in &["", "players", "matches", "maps"] {
+            for entry in fs::read_dir(dir.path().join(sub)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    continue;
+                }
+                let page = fs::read_to_string(&path).unwrap();
+                assert!(!page.contains(NASTY), "{} isn't escaped", path.display());
+            }
+        }
+    }
+}
@@ -0,0 +1,1029 @@
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use checkpoint::FileCheckpoint;
+use error::LogError;
+use event::SquadEvent;
+use identity::{IdentityRegistry, OnlineIds};
+use overrides::IdentityOverrides;
+use parse::{get_dt, ParsedLine};
+use schema::STATE_VERSION;
+use timezones::{in_zone, Timezones};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum PlayerState {
+    Playing,
+    Inactive,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Player {
+    pub name: String,
+    pub state: PlayerState,
+    pub hitpoints: f32,
+    pub last_damaged: Option<String>,
+    pub last_spawn_time: Option<DateTime<FixedOffset>>,
+    pub last_down_time: Option<DateTime<FixedOffset>>,
+    pub players_killed_by: HashMap<String, u32>,
+    pub players_killed: HashMap<String, u32>,
+    pub classes_played: HashSet<String>,
+    pub players_revived_by: HashMap<String, u32>,
+    pub players_revived: HashMap<String, u32>,
+    // Every revive this player performed, in order.
+    #[serde(default)]
+    pub revives: Vec<Revive>,
+    // How many times this player went down, whether or not we know who did it.
+    #[serde(default)]
+    pub downs: u32,
+    // How many times this player died, finished off or giving up while down.
+    #[serde(default)]
+    pub deaths: u32,
+    // Keyed by the weapon name from the "caused by" part of the log line.
+    #[serde(default)]
+    pub weapons: HashMap<String, WeaponStats>,
+    // Who this player is across games, from the IdentityRegistry, if we know.
+    #[serde(default)]
+    pub identity: Option<String>,
+}
+impl PartialEq for Player {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+impl Eq for Player {}
+impl Hash for Player {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct WeaponStats {
+    pub damage_dealt: f32,
+    pub downs_inflicted: u32,
+    pub downs_suffered: u32,
+}
+
+impl WeaponStats {
+    pub fn add(&mut self, other: &WeaponStats) {
+        self.damage_dealt += other.damage_dealt;
+        self.downs_inflicted += other.downs_inflicted;
+        self.downs_suffered += other.downs_suffered;
+    }
+}
+
+// A player's weapons in name order. Float sums taken in this order come out the
+// same every run, which they wouldn't in the map's own order.
+pub fn weapons_by_name(weapons: &HashMap<String, WeaponStats>) -> Vec<(&String, &WeaponStats)> {
+    let mut weapons: Vec<(&String, &WeaponStats)> = weapons.iter().collect();
+    weapons.sort_by_key(|(name, _)| *name);
+    weapons
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Revive {
+    pub revivee: String,
+    pub time: DateTime<FixedOffset>,
+    // Seconds between the revivee going down and getting picked up, if we saw
+    // them go down.
+    pub time_down: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Game {
+    pub map: String,
+    pub players: HashMap<String, Player>,
+    pub start_time: DateTime<FixedOffset>,
+    // When the match ended, if we saw it end.
+    #[serde(default)]
+    pub end_time: Option<DateTime<FixedOffset>>,
+    // The log format generation the game was parsed with. Games from before
+    // we recorded it have None.
+    #[serde(default)]
+    pub generation: Option<String>,
+    // The zone the server logged in, and the one reports show the game in.
+    // start_time is stored with its offset either way.
+    #[serde(default)]
+    pub source_timezone: Option<Tz>,
+    #[serde(default)]
+    pub display_timezone: Option<Tz>,
+    // The time of the last game event applied to this game, and how many
+    // were applied at that time. When the game's log is read again, events up
+    // to there were already counted. Games from before we kept this have
+    // None, and all their events are taken to be counted.
+    #[serde(default)]
+    pub applied_through: Option<(DateTime<FixedOffset>, u32)>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GameState {
+    // The statefile version, which is always STATE_VERSION once loaded.
+    #[serde(default)]
+    pub version: u32,
+    pub games: Vec<Game>, // Sorted by start_time, from earliest to latest.
+    pub current_game_start_time: DateTime<FixedOffset>,
+    pub last_timestamp: DateTime<FixedOffset>,
+    pub player_names: Vec<(String, Option<String>)>,
+    #[serde(default)]
+    pub files: Vec<FileCheckpoint>,
+    // Unlike player_names, this is kept across games.
+    #[serde(default)]
+    pub identities: IdentityRegistry,
+    // IDs from a login we haven't seen the matching join for yet.
+    #[serde(default)]
+    pub pending_login: Option<OnlineIds>,
+    // The controller from that login.
+    #[serde(default)]
+    pub pending_controller: Option<String>,
+    // The name each controller joined as, for lines that print a player's
+    // controller rather than their name.
+    #[serde(default)]
+    pub controllers: HashMap<String, String>,
+    // How much of the event log next to the statefile belongs to it, and
+    // whether the log goes back to when the statefile was empty.
+    #[serde(default)]
+    pub events_len: u64,
+    #[serde(default)]
+    pub events_complete: bool,
+    // A hash of the end of the committed event log, so a statefile put back
+    // from a backup can tell whether the log still holds its events.
+    #[serde(default)]
+    pub events_tail: Option<u64>,
+    // Loaded fresh from the admin's alias file on every run, if there is one.
+    #[serde(skip)]
+    pub overrides: IdentityOverrides,
+    // The format generation of the log being applied, set by whoever feeds
+    // us its lines.
+    #[serde(skip)]
+    pub generation: Option<String>,
+    // Like overrides, loaded fresh on every run.
+    #[serde(skip)]
+    pub timezones: Timezones,
+    // The zone of the log being applied.
+    #[serde(skip)]
+    pub source_timezone: Option<Tz>,
+    // The current game was already in the state when its map loaded, so we
+    // are reading its log a second time and are still among events that were
+    // counted the first time.
+    #[serde(default)]
+    pub replaying: bool,
+    // How many events at the current game's applied_through time we have
+    // skipped while replaying.
+    #[serde(default)]
+    pub replay_skipped: u32,
+}
+
+impl GameState {
+    // An empty state, for when there is no statefile yet.
+    pub fn new() -> GameState {
+        GameState {
+            version: STATE_VERSION,
+            games: Vec::new(),
+            current_game_start_time: get_dt("1941.12.07-07.00.00:000").unwrap(),
+            last_timestamp: get_dt("1941.12.7-07.00.00:000").unwrap(),
+            player_names: Vec::new(),
+            files: Vec::new(),
+            identities: IdentityRegistry::default(),
+            pending_login: None,
+            pending_controller: None,
+            controllers: HashMap::new(),
+            events_len: 0,
+            events_complete: true,
+            events_tail: None,
+            overrides: IdentityOverrides::default(),
+            generation: None,
+            timezones: Timezones::default(),
+            source_timezone: None,
+            replaying: false,
+            replay_skipped: 0,
+        }
+    }
+}
+
+impl Default for GameState {
+    fn default() -> GameState {
+        GameState::new()
+    }
+}
+
+// Where one log was up to in its own game: the game it was in, the
+// controllers that had connected, and whether it was replaying a game we
+// already had. A run can read several logs, say from two servers, so this is
+// kept with each log's checkpoint and put back before the log is read further.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FileContext {
+    pub current_game_start_time: DateTime<FixedOffset>,
+    pub player_names: Vec<(String, Option<String>)>,
+    pub pending_login: Option<OnlineIds>,
+    pub pending_controller: Option<String>,
+    pub controllers: HashMap<String, String>,
+    pub replaying: bool,
+    pub replay_skipped: u32,
+}
+
+// The context of a log we haven't read any of, which is in no game yet.
+impl Default for FileContext {
+    fn default() -> FileContext {
+        GameState::new().file_context()
+    }
+}
+
+impl GameState {
+    // The context of the log we have been applying.
+    pub fn file_context(&self) -> FileContext {
+        FileContext {
+            current_game_start_time: self.current_game_start_time,
+            player_names: self.player_names.clone(),
+            pending_login: self.pending_login.clone(),
+            pending_controller: self.pending_controller.clone(),
+            controllers: self.controllers.clone(),
+            replaying: self.replaying,
+            replay_skipped: self.replay_skipped,
+        }
+    }
+
+    // Carry on applying a log from where context left it.
+    pub fn enter_file(&mut self, context: &FileContext) {
+        self.current_game_start_time = context.current_game_start_time;
+        self.player_names = context.player_names.clone();
+        self.pending_login = context.pending_login.clone();
+        self.pending_controller = context.pending_controller.clone();
+        self.controllers = context.controllers.clone();
+        self.replaying = context.replaying;
+        self.replay_skipped = context.replay_skipped;
+    }
+}
+
+// Remember a controller name we have seen connect, so damage and downs can be
+// resolved against it later.
+fn seen_player_name(name: &str, names: &mut Vec<(String, Option<String>)>) {
+    for (left, right) in names.iter() {
+        if left == name && right.is_some() {
+            return;
+        }
+    }
+
+    names.push((String::from(name), None));
+}
+
+// Match a name from the log, which may have a clan tag in front, to the name
+// of a controller we have seen connect.
+fn try_lookup_player_name(name: &str, names: &mut [(String, Option<String>)]) -> Option<String> {
+    let mut res = None;
+    for (left, right) in names.iter() {
+        if let Some(realname) = right {
+            if name == realname {
+                res = Some(left);
+            }
+        }
+    }
+
+    match res {
+        Some(n) => Some(n.clone()),
+        None => {
+            // Compute the length of the names in counts of characters rather
+            // than byte lengths of the strings.
+            let name_len = name.chars().count();
+            // If one controller's name ends another's, as "Bob" ends "Jim
+            // Bob", the longer one is the better match.
+            let mut best: Option<(usize, usize)> = None;
+            for (i, (left, _)) in names.iter().enumerate() {
+                let left_len = left.chars().count();
+                if left_len <= name_len && best.is_none_or(|(_, len)| left_len > len) {
+                    let s1: String = name.chars().skip(name_len - left_len).collect();
+                    if s1 == *left {
+                        best = Some((i, left_len));
+                    }
+                }
+            }
+
+            best.map(|(i, _)| {
+                names[i].1 = Some(String::from(name));
+                names[i].0.clone()
+            })
+        }
+    }
+}
+
+// t as it should be shown in reports about game.
+pub fn display_time(game: &Game, t: &DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+    match game.display_timezone {
+        Some(zone) => in_zone(t, zone),
+        None => *t,
+    }
+}
+
+// Who a player in game really is, once the admin's overrides are taken into
+// account. None means we only know them by name.
+pub fn effective_identity(g: &GameState, game: &Game, player: &Player) -> Option<String> {
+    g.overrides.identity_of(
+        &g.identities,
+        &player.name,
+        player.identity.as_ref(),
+        &game.start_time,
+    )
+}
+
+// The player in the game at game_idx that belongs to the same identity as
+// name, if we know who name is and they are in that game.
+fn resolve_by_identity(name: &str, game_idx: usize, g: &GameState) -> Option<String> {
+    let game = &g.games[game_idx];
+    let identity = g.overrides.identity_of(
+        &g.identities,
+        name,
+        g.identities.identity_of_name(name),
+        &game.start_time,
+    )?;
+    game.players
+        .iter()
+        .find(|(_n, p)| effective_identity(g, game, p).as_ref() == Some(&identity))
+        .map(|(n, _p)| n.clone())
+}
+
+// Work out which player in the current game a name from the log refers to.
+// A controller is first swapped for the name it joined as. Names we have
+// online IDs for are resolved through the identity registry, anything else
+// by matching clan-tagged names against controller names.
+fn try_resolve_player_name(name: &str, game_idx: usize, g: &mut GameState) -> Option<String> {
+    let name = match g.controllers.get(name) {
+        Some(joined_as) => joined_as.clone(),
+        None => String::from(name),
+    };
+    match resolve_by_identity(&name, game_idx, g) {
+        Some(n) => Some(n),
+        None => try_lookup_player_name(&name, &mut g.player_names),
+    }
+}
+
+// Like try_resolve_player_name, for names we have to know.
+fn resolve_player_name(name: &str, game_idx: usize, g: &mut GameState) -> Result<String, LogError> {
+    match try_resolve_player_name(name, game_idx, g) {
+        Some(n) => Ok(n),
+        None => Err(LogError::UnknownPlayer(String::from(name))),
+    }
+}
+
+fn game_ended(timestamp: &DateTime<FixedOffset>, game: &mut Game) {
+    game.end_time = Some(*timestamp);
+}
+
+// Drop the games that started before cutoff. Returns how many went.
+pub fn prune_games(g: &mut GameState, cutoff: &DateTime<FixedOffset>) -> usize {
+    let n = g.games.partition_point(|game| game.start_time < *cutoff);
+    g.games.drain(..n);
+    // Rebuilding from the event log would bring them back.
+    if n > 0 {
+        g.events_complete = false;
+    }
+    n
+}
+
+// Game state helper routines.
+
+// The game the log is in the middle of, or that it last ended.
+pub fn current_game(g: &GameState) -> Option<&Game> {
+    get_current_game_idx(g).ok().map(|idx| &g.games[idx])
+}
+
+// The current game is the one that started at the time indicated by current_game_start_time.
+fn get_current_game_idx(g: &GameState) -> Result<usize, LogError> {
+    g.games
+        .binary_search_by_key(&g.current_game_start_time, |t| t.start_time)
+        .map_err(|_e| LogError::NoGame)
+}
+
+// Game state updating routines. These all update the state in place, and only
+// ever touch the current game, so the cost of a line doesn't depend on how
+// much history is in the state. They check that the players they need are in
+// the game before changing it, so an event that fails leaves the game as it
+// was. What we learn about names along the way is kept either way: resolving
+// a clan-tagged name records which controller it belongs to, even if another
+// name in the same event then can't be resolved.
+
+// Update that one player revived another.
+fn player_revived(
+    timestamp: &DateTime<FixedOffset>,
+    reviving: &str,
+    revived: &str,
+    g: &mut GameState,
+) -> Result<(), LogError> {
+    let game_idx = get_current_game_idx(g)?;
+    let resolved_reviver_name = resolve_player_name(reviving, game_idx, g)?;
+    let resolved_revivee_name = resolve_player_name(revived, game_idx, g)?;
+    let current_game = &mut g.games[game_idx];
+
+    // Both players have to be in the game for the revive to count.
+    if !current_game.players.contains_key(&resolved_reviver_name) {
+        return Ok(());
+    }
+
+    let time_down = match current_game.players.get_mut(&resolved_revivee_name) {
+        Some(revivee) => {
+            *revivee
+                .players_revived_by
+                .entry(resolved_reviver_name.clone())
+                .or_insert(0) += 1;
+            revivee.hitpoints = 5.0;
+            revivee
+                .last_down_time
+                .map(|t| (*timestamp - t).num_milliseconds() as f64 / 1000.0)
+        }
+        None => return Ok(()),
+    };
+
+    let reviver = current_game
+        .players
+        .get_mut(&resolved_reviver_name)
+        .unwrap();
+    *reviver
+        .players_revived
+        .entry(resolved_revivee_name.clone())
+        .or_insert(0) += 1;
+    reviver.revives.push(Revive {
+        revivee: resolved_revivee_name,
+        time: *timestamp,
+        time_down,
+    });
+    Ok(())
+}
+
+// Add a player to the game state.
+fn player_spawned(
+    timestamp: &DateTime<FixedOffset>,
+    name: &str,
+    class: &str,
+    g: &mut GameState,
+) -> Result<(), LogError> {
+    let game_idx = get_current_game_idx(g)?;
+    g.identities.seen(timestamp, name);
+    let current_game = &mut g.games[game_idx];
+
+    // See if the player is in the current_game player hash set.
+    match current_game.players.get_mut(name) {
+        Some(player) => {
+            // A player existed, update what classes they have played and their last
+            // spawn time
+            player.state = PlayerState::Playing;
+            player.classes_played.insert(String::from(class));
+            player.hitpoints = 100.0;
+            player.last_damaged = None;
+            player.last_spawn_time = Some(*timestamp);
+        }
+        None => {
+            let mut classes_played = HashSet::new();
+            classes_played.insert(String::from(class));
+
+            let new_player = Player {
+                name: String::from(name),
+                state: PlayerState::Inactive,
+                classes_played,
+                hitpoints: 100.0,
+                last_damaged: None,
+                last_down_time: None,
+                last_spawn_time: Some(*timestamp),
+                players_killed: HashMap::new(),
+                players_killed_by: HashMap::new(),
+                players_revived: HashMap::new(),
+                players_revived_by: HashMap::new(),
+                revives: Vec::new(),
+                downs: 0,
+                deaths: 0,
+                weapons: HashMap::new(),
+                identity: g.identities.identity_of_name(name).cloned(),
+            };
+            current_game.players.insert(String::from(name), new_player);
+        }
+    }
+    Ok(())
+}
+
+// Called when a new map is loaded. Games are told apart by when they started,
+// as in merge_state, so a game we already have is replayed rather than added
+// twice.
+fn starting_game(timestamp: &DateTime<FixedOffset>, map_name: &str, g: &mut GameState) {
+    g.current_game_start_time = *timestamp;
+    g.replaying = g
+        .games
+        .binary_search_by_key(timestamp, |t| t.start_time)
+        .is_ok();
+    g.replay_skipped = 0;
+    if g.replaying {
+        return;
+    }
+
+    // Make a new Game.
+    let new_game = Game {
+        map: String::from(map_name),
+        players: HashMap::new(),
+        start_time: *timestamp,
+        end_time: None,
+        generation: g.generation.clone(),
+        source_timezone: g.source_timezone,
+        display_timezone: Some(g.timezones.display_zone()),
+        applied_through: Some((*timestamp, 0)),
+    };
+    // Logs can be ingested in any order, so keep games sorted by inserting
+    // rather than pushing.
+    let idx = g.games.partition_point(|t| t.start_time <= *timestamp);
+    g.games.insert(idx, new_game);
+}
+
+fn player_damaged(
+    _timestamp: &DateTime<FixedOffset>,
+    shooter: &str,
+    damage: f32,
+    target: &str,
+    weapon: &str,
+    g: &mut GameState,
+) -> Result<(), LogError> {
+    let game_idx = get_current_game_idx(g)?;
+    let resolved_name = resolve_player_name(target, game_idx, g)?;
+    let resolved_shooter_name = if shooter != "nullptr" {
+        try_resolve_player_name(shooter, game_idx, g)
+    } else {
+        None
+    };
+    let current_game = &mut g.games[game_idx];
+    if !current_game.players.contains_key(&resolved_name) {
+        return Err(LogError::NotSpawned(resolved_name));
+    }
+
+    // Credit the damage to the weapon of whoever did it, if we know them.
+    if let Some(shooter_name) = resolved_shooter_name {
+        if let Some(shooting_player) = current_game.players.get_mut(&shooter_name) {
+            shooting_player
+                .weapons
+                .entry(String::from(weapon))
+                .or_default()
+                .damage_dealt += damage;
+        }
+    }
+
+    let retrieved_player = current_game.players.get_mut(&resolved_name).unwrap();
+
+    // If we know who did the damage, mark that in the player state for the player
+    // that was shot.
+    if shooter != "nullptr" {
+        retrieved_player.last_damaged = Some(String::from(shooter));
+    }
+    retrieved_player.hitpoints -= damage;
+    Ok(())
+}
+
+fn player_down(
+    timestamp: &DateTime<FixedOffset>,
+    player: &str,
+    attacker: &str,
+    weapon: &str,
+    g: &mut GameState,
+) -> Result<(), LogError> {
+    let game_idx = get_current_game_idx(g)?;
+    let resolved_player_name = resolve_player_name(player, game_idx, g)?;
+
+    // Who was the player last shot by? If no damage line said, the wound
+    // line's attacker. The down counts even if we can't tell, or they aren't
+    // in the game; only crediting it to them is skipped.
+    let last_damaged = match g.games[game_idx].players.get(&resolved_player_name) {
+        Some(p) => p
+            .last_damaged
+            .clone()
+            .or_else(|| Some(String::from(attacker)).filter(|a| a != "nullptr")),
+        None => return Err(LogError::NotSpawned(resolved_player_name)),
+    };
+    let resolved_killer_name =
+        last_damaged.and_then(|killer_name| try_resolve_player_name(&killer_name, game_idx, g));
+    let current_game = &mut g.games[game_idx];
+    let resolved_killer_name =
+        resolved_killer_name.filter(|killer_name| current_game.players.contains_key(killer_name));
+
+    let downed_player = current_game.players.get_mut(&resolved_player_name).unwrap();
+    downed_player.downs += 1;
+    downed_player.last_down_time = Some(*timestamp);
+    downed_player.last_damaged = None;
+    downed_player
+        .weapons
+        .entry(String::from(weapon))
+        .or_default()
+        .downs_suffered += 1;
+
+    // Update the killer's stats, too.
+    if let Some(resolved_killer_name) = resolved_killer_name {
+        let killing_player = current_game.players.get_mut(&resolved_killer_name).unwrap();
+        *killing_player
+            .players_killed
+            .entry(resolved_player_name.clone())
+            .or_insert(0) += 1;
+        killing_player
+            .weapons
+            .entry(String::from(weapon))
+            .or_default()
+            .downs_inflicted += 1;
+
+        let downed_player = current_game.players.get_mut(&resolved_player_name).unwrap();
+        *downed_player
+            .players_killed_by
+            .entry(resolved_killer_name)
+            .or_insert(0) += 1;
+    }
+    Ok(())
+}
+
+fn player_died(
+    _timestamp: &DateTime<FixedOffset>,
+    player: &str,
+    g: &mut GameState,
+) -> Result<(), LogError> {
+    let game_idx = get_current_game_idx(g)?;
+    let resolved_player_name = resolve_player_name(player, game_idx, g)?;
+    match g.games[game_idx].players.get_mut(&resolved_player_name) {
+        Some(p) => p.deaths += 1,
+        None => return Err(LogError::NotSpawned(resolved_player_name)),
+    }
+    Ok(())
+}
+
+fn match_state_changed(
+    timestamp: &DateTime<FixedOffset>,
+    to: &str,
+    g: &mut GameState,
+) -> Result<(), LogError> {
+    if to == "WaitingPostMatch" && !g.games.is_empty() {
+        let game_idx = get_current_game_idx(g)?;
+        game_ended(timestamp, &mut g.games[game_idx]);
+        g.player_names.clear();
+    }
+    Ok(())
+}
+
+// Record that name has these online IDs, and make sure every player stat we
+// have agrees with the registry about who is who.
+fn player_identified(
+    timestamp: &DateTime<FixedOffset>,
+    name: &str,
+    ids: &OnlineIds,
+    g: &mut GameState,
+) {
+    let (identity, merged) = match g.identities.identify(timestamp, name, ids) {
+        Some(t) => t,
+        None => return,
+    };
+
+    // The IDs showed two identities are the same person, so fold the old one
+    // into the new one everywhere. This is rare, so walking all of history
+    // is fine.
+    if let Some(old) = merged {
+        for game in g.games.iter_mut() {
+            for player in game.players.values_mut() {
+                if player.identity.as_ref() == Some(&old) {
+                    player.identity = Some(identity.clone());
+                }
+            }
+        }
+    }
+
+    if let Ok(game_idx) = get_current_game_idx(g) {
+        if let Some(player) = g.games[game_idx].players.get_mut(name) {
+            player.identity = Some(identity);
+        }
+    }
+}
+
+// Whether event changes the current game, rather than who is connected or
+// who is who.
+fn is_game_event(event: &SquadEvent) -> bool {
+    match event {
+        SquadEvent::Revived { .. }
+        | SquadEvent::Damaged { .. }
+        | SquadEvent::RoleChanged { .. }
+        | SquadEvent::Wounded { .. }
+        | SquadEvent::Died { .. } => true,
+        // Ending a game we already have sets the same end time again.
+        SquadEvent::MatchStateChanged { .. }
+        | SquadEvent::ControllerStateChanged { .. }
+        | SquadEvent::MapLoading { .. }
+        | SquadEvent::PlayerLoggedIn { .. }
+        | SquadEvent::PlayerJoined { .. }
+        | SquadEvent::PlayerIdentified { .. } => false,
+    }
+}
+
+// Whether event is one the current game counted the first time its log was
+// read. Events are applied in log order, so those are the ones before the
+// game's applied_through time, and as many as were applied at that time. A log
+// that was cut short the first time, say a plain log later read again from an
+// archive, picks up after them.
+fn already_applied(event: &SquadEvent, g: &mut GameState) -> bool {
+    if !g.replaying {
+        return false;
+    }
+    let (last, count) = match get_current_game_idx(g) {
+        Ok(idx) => match g.games[idx].applied_through {
+            Some(through) => through,
+            None => return true,
+        },
+        Err(_e) => return false,
+    };
+    let timestamp = event.timestamp();
+    if timestamp < last {
+        return true;
+    }
+    if timestamp == last && g.replay_skipped < count {
+        g.replay_skipped += 1;
+        return true;
+    }
+    g.replaying = false;
+    false
+}
+
+fn mark_applied(timestamp: &DateTime<FixedOffset>, g: &mut GameState) {
+    if let Ok(idx) = get_current_game_idx(g) {
+        let through = &mut g.games[idx].applied_through;
+        *through = match *through {
+            Some((last, count)) if last == *timestamp => Some((last, count + 1)),
+            Some((last, count)) if last > *timestamp => Some((last, count)),
+            _ => Some((*timestamp, 1)),
+        };
+    }
+}
+
+// Apply a single event to the game state.
+pub fn apply_event(event: &SquadEvent, g: &mut GameState) -> Result<(), LogError> {
+    let game_event = is_game_event(event);
+    if game_event && already_applied(event, g) {
+        return Ok(());
+    }
+    match event {
+        SquadEvent::Revived {
+            timestamp,
+            reviver,
+            revivee,
+        } => player_revived(timestamp, reviver, revivee, g)?,
+        // Sometimes, someone damages nullptr. Ignore that.
+        SquadEvent::Damaged {
+            timestamp,
+            victim,
+            damage,
+            attacker,
+            weapon,
+        } => {
+            if victim != "nullptr" {
+                player_damaged(timestamp, attacker, *damage, victim, weapon, g)?
+            }
+        }
+        SquadEvent::RoleChanged {
+            timestamp,
+            player,
+            role,
+        } => {
+            if role != "nullptr" {
+                player_spawned(timestamp, player, role, g)?
+            }
+        }
+        SquadEvent::Wounded {
+            timestamp,
+            victim,
+            attacker,
+            weapon,
+            ..
+        } => {
+            if victim != "nullptr" {
+                player_down(timestamp, victim, attacker, weapon, g)?
+            }
+        }
+        SquadEvent::Died {
+            timestamp, victim, ..
+        } => {
+            if victim != "nullptr" {
+                player_died(timestamp, victim, g)?
+            }
+        }
+        SquadEvent::ControllerStateChanged { controller, .. } => {
+            seen_player_name(controller, &mut g.player_names)
+        }
+        SquadEvent::MatchStateChanged { timestamp, to, .. } => {
+            match_state_changed(timestamp, to, g)?
+        }
+        SquadEvent::MapLoading { timestamp, map } => starting_game(timestamp, map, g),
+        SquadEvent::PlayerLoggedIn {
+            controller, ids, ..
+        } => {
+            g.pending_login = Some(ids.clone());
+            g.pending_controller = Some(controller.clone());
+        }
+        SquadEvent::PlayerJoined { timestamp, name } => {
+            if let Some(controller) = g.pending_controller.take() {
+                g.controllers.insert(controller, name.clone());
+            }
+            if let Some(ids) = g.pending_login.take() {
+                player_identified(timestamp, name, &ids, g)
+            }
+        }
+        SquadEvent::PlayerIdentified {
+            timestamp,
+            name,
+            ids,
+        } => player_identified(timestamp, name, ids, g),
+    }
+    if game_event {
+        mark_applied(&event.timestamp(), g);
+    }
+    Ok(())
+}
+
+// Apply everything parsed from one line. Which lines have already been
+// applied is tracked per file by byte offset (see checkpoint), so every line
+// handed to us here is new. Stops at the first event that can't be applied;
+// the events before it, like the PlayerIdentified events that come ahead of a
+// damage or wound on newer logs, stay applied.
+pub fn apply_line(line: &ParsedLine, g: &mut GameState) -> Result<(), LogError> {
+    if line.timestamp > g.last_timestamp {
+        g.last_timestamp = line.timestamp;
+    }
+
+    for event in &line.events {
+        apply_event(event, g)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<FixedOffset> {
+        get_dt(s).unwrap()
+    }
+
+    fn apply_all(events: &[SquadEvent], g: &mut GameState) {
+        for event in events {
+            apply_event(event, g).unwrap();
+        }
+    }
+
+    // A game on Narva with Alice and Bob spawned, then Alice wounding Bob.
+    fn narva(start: &str) -> Vec<SquadEvent> {
+        let t = at(start);
+        let mut events = vec![SquadEvent::MapLoading {
+            timestamp: t,
+            map: String::from("Narva"),
+        }];
+        for name in &["Alice", "Bob"] {
+            events.push(SquadEvent::ControllerStateChanged {
+                timestamp: t,
+                controller: String::from(*name),
+                old_state: String::from("Inactive"),
+                new_state: String::from("Playing"),
+            });
+            events.push(SquadEvent::RoleChanged {
+                timestamp: t,
+                player: String::from(*name),
+                role: String::from("USA_Rifleman_01"),
+            });
+        }
+        events.push(SquadEvent::Damaged {
+            timestamp: t,
+            victim: String::from("Bob"),
+            damage: 100.0,
+            attacker: String::from("Alice"),
+            weapon: String::from("BP_M4_C_1"),
+        });
+        events.push(SquadEvent::Wounded {
+            timestamp: t,
+            victim: String::from("Bob"),
+            damage: 100.0,
+            attacker: String::from("Alice"),
+            weapon: String::from("BP_M4_C_1"),
+        });
+        events
+    }
+
+    #[test]
+    fn counts_a_down_against_whoever_last_did_damage() {
+        let mut g = GameState::new();
+        apply_all(&narva("2026.10.01-10.00.00:000"), &mut g);
+        let bob = &g.games[0].players["Bob"];
+        assert_eq!(bob.downs, 1);
+        assert_eq!(bob.players_killed_by["Alice"], 1);
+        assert_eq!(g.games[0].players["Alice"].players_killed["Bob"], 1);
+    }
+
+    #[test]
+    fn reading_a_game_again_doesnt_count_it_twice() {
+        let mut g = GameState::new();
+        apply_all(&narva("2026.10.01-10.00.00:000"), &mut g);
+        apply_all(&narva("2026.10.02-10.00.00:000"), &mut g);
+        apply_all(&narva("2026.10.01-10.00.00:000"), &mut g);
+
+        assert_eq!(g.games.len(), 2);
+        assert!(g.replaying);
+        assert_eq!(g.games[0].players["Bob"].downs, 1);
+
+        apply_all(&narva("2026.10.03-10.00.00:000"), &mut g);
+        assert!(!g.replaying);
+        assert_eq!(g.games.len(), 3);
+        assert_eq!(g.games[2].players["Bob"].downs, 1);
+    }
+
+    #[test]
+    fn reading_a_game_again_picks_up_where_the_first_read_stopped() {
+        // The plain log was read while the server was still writing it, and
+        // stopped before Bob went down. The archive has the whole game.
+        let game = narva("2026.10.01-10.00.00:000");
+        let mut g = GameState::new();
+        apply_all(&game[..game.len() - 1], &mut g);
+        assert_eq!(g.games[0].players["Bob"].downs, 0);
+
+        apply_all(&game, &mut g);
+        assert!(!g.replaying);
+        let bob = &g.games[0].players["Bob"];
+        assert_eq!(bob.downs, 1);
+        assert_eq!(bob.hitpoints, 0.0);
+        assert_eq!(bob.players_killed_by["Alice"], 1);
+        assert_eq!(
+            g.games[0].players["Alice"].weapons["BP_M4_C_1"].damage_dealt,
+            100.0
+        );
+    }
+
+    #[test]
+    fn counts_a_down_whose_attacker_cant_be_resolved() {
+        let mut g = GameState::new();
+        let mut events = narva("2026.10.01-10.00.00:000");
+        let wound = events.pop().unwrap();
+        let t = at("2026.10.01-10.00.00:000");
+        events.push(SquadEvent::Damaged {
+            timestamp: t,
+            victim: String::from("Bob"),
+            damage: 100.0,
+            attacker: String::from("Zed"),
+            weapon: String::from("BP_M4_C_1"),
+        });
+        events.push(wound);
+        apply_all(&events, &mut g);
+
+        let bob = &g.games[0].players["Bob"];
+        assert_eq!(bob.downs, 1);
+        assert!(bob.players_killed_by.is_empty());
+        assert!(bob.last_damaged.is_none());
+    }
+
+    #[test]
+    fn rejects_damage_to_someone_unknown() {
+        let mut g = GameState::new();
+        apply_all(&narva("2026.10.01-10.00.00:000"), &mut g);
+        let damage = SquadEvent::Damaged {
+            timestamp: at("2026.10.01-10.01.00:000"),
+            victim: String::from("Zed"),
+            damage: 10.0,
+            attacker: String::from("Alice"),
+            weapon: String::from("BP_M4_C_1"),
+        };
+        assert_eq!(
+            apply_event(&damage, &mut g),
+            Err(LogError::UnknownPlayer(String::from("Zed")))
+        );
+    }
+
+    #[test]
+    fn prefers_the_longest_name_a_tagged_name_ends_with() {
+        let t = at("2026.10.01-10.00.00:000");
+        let mut events = vec![SquadEvent::MapLoading {
+            timestamp: t,
+            map: String::from("Narva"),
+        }];
+        for name in &["Alice", "Jim Bob", "Bob"] {
+            events.push(SquadEvent::ControllerStateChanged {
+                timestamp: t,
+                controller: String::from(*name),
+                old_state: String::from("Inactive"),
+                new_state: String::from("Playing"),
+            });
+            events.push(SquadEvent::RoleChanged {
+                timestamp: t,
+                player: String::from(*name),
+                role: String::from("USA_Rifleman_01"),
+            });
+        }
+        for victim in &["[T] Jim Bob", "Bob"] {
+            events.push(SquadEvent::Wounded {
+                timestamp: t,
+                victim: String::from(*victim),
+                damage: 100.0,
+                attacker: String::from("Alice"),
+                weapon: String::from("BP_M4_C_1"),
+            });
+        }
+        let mut g = GameState::new();
+        apply_all(&events, &mut g);
+
+        let players = &g.games[0].players;
+        assert_eq!(players["Jim Bob"].downs, 1);
+        assert_eq!(players["Bob"].downs, 1);
+        assert_eq!(players["Alice"].players_killed["Jim Bob"], 1);
+        assert_eq!(players["Alice"].players_killed["Bob"], 1);
+    }
+}
@@ -0,0 +1,214 @@
+use fs2::FileExt;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::Builder;
+
+use eventlog::tail_hash;
+use schema::load_state;
+use state::GameState;
+
+// How many old statefiles save_state keeps unless told otherwise.
+pub const DEFAULT_BACKUPS: usize = 3;
+
+// path with suffix added to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// The nth most recent backup of the statefile at path, counting from 1.
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    with_suffix(path, &format!(".{}", n))
+}
+
+// The event log that goes with the statefile at path.
+pub fn event_log_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".events")
+}
+
+// The backups of the statefile at path that exist, most recent first.
+pub fn list_backups(path: &Path, backups: usize) -> Vec<PathBuf> {
+    (1..=backups)
+        .map(|n| backup_path(path, n))
+        .filter(|p| p.is_file())
+        .collect()
+}
+
+// Held for as long as we are working on a statefile, so two runs can't both
+// load it and then save over each other. The lock is on a file next to the
+// statefile, since saving replaces the statefile itself.
+pub struct StateLock {
+    _file: File,
+}
+
+pub fn lock_statefile(path: &Path) -> io::Result<StateLock> {
+    let lock_path = with_suffix(path, ".lock");
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)?;
+    if file.try_lock_exclusive().is_err() {
+        return Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!(
+                "{} is locked by another squadlog; wait for it to finish",
+                path.display()
+            ),
+        ));
+    }
+    Ok(StateLock { _file: file })
+}
+
+// Shift the backups of path along by one, dropping the oldest, and make the
+// current statefile the most recent backup.
+fn rotate_backups(path: &Path, backups: usize) -> io::Result<()> {
+    if backups == 0 || !path.is_file() {
+        return Ok(());
+    }
+    for n in (1..backups).rev() {
+        let from = backup_path(path, n);
+        if from.is_file() {
+            fs::rename(&from, backup_path(path, n + 1))?;
+        }
+    }
+    // A link leaves the statefile in place until the new one replaces it.
+    let newest = backup_path(path, 1);
+    if fs::hard_link(path, &newest).is_err() {
+        fs::copy(path, &newest)?;
+    }
+    Ok(())
+}
+
+// Replace the file at path with contents. They go to a temporary file next to
+// it first, which is flushed to disk and renamed over path, so a crash leaves
+// either the old file or the new one and never half of either.
+pub fn write_atomic(path: &Path, contents: &[u8], backups: usize) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    // Temporary files are only readable by us. A new file gets what the
+    // umask allows, as it would from fs::write, and one that replaces another
+    // keeps whatever that one allowed.
+    let mut builder = Builder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+    let mut tmp = builder.tempfile_in(dir)?;
+    tmp.write_all(contents)?;
+    tmp.as_file().sync_all()?;
+    if let Ok(meta) = fs::metadata(path) {
+        fs::set_permissions(tmp.path(), meta.permissions())?;
+    }
+
+    rotate_backups(path, backups)?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    // The rename only survives a crash once the directory is on disk too.
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+pub fn save_state(path: &Path, g: &GameState, backups: usize) -> io::Result<()> {
+    let contents = serde_json::to_string(g)?;
+    write_atomic(path, contents.as_bytes(), backups)
+}
+
+// Put the nth backup back as the statefile. The statefile it replaces becomes
+// the most recent backup, so a restore can itself be undone.
+//
+// The event log is shared by every backup. If it has been cut back and added
+// to since the backup was saved, it no longer holds the backup's events, so
+// the backup is put back to go on from the log as the current statefile left
+// it, and can't be rebuilt. Returns false if that happened.
+pub fn restore_backup(path: &Path, n: usize, backups: usize) -> io::Result<bool> {
+    let backup = backup_path(path, n);
+    let contents = fs::read_to_string(&backup)?;
+    // Don't put back something we couldn't load.
+    let mut g = load_state(&contents)?;
+    let log = event_log_path(path);
+    if g.events_len == 0
+        || (g.events_tail.is_some() && tail_hash(&log, g.events_len)? == g.events_tail)
+    {
+        write_atomic(path, contents.as_bytes(), backups)?;
+        return Ok(true);
+    }
+
+    let current = match fs::read_to_string(path) {
+        Ok(contents) => load_state(&contents)?,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => GameState::new(),
+        Err(e) => return Err(e),
+    };
+    g.events_len = current.events_len;
+    g.events_tail = current.events_tail;
+    g.events_complete = false;
+    save_state(path, &g, backups)?;
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event::SquadEvent;
+    use eventlog::EventLog;
+    use parse::get_dt;
+    use tempfile::tempdir;
+
+    // Cut the event log back to len, add a line to it and save the statefile
+    // that goes with that.
+    fn save_with_line(path: &Path, len: u64, map: &str) -> GameState {
+        let log = event_log_path(path);
+        let timestamp = get_dt("2026.10.01-10.00.00:000").unwrap();
+        let mut events = EventLog::open(&log, len).unwrap();
+        events
+            .record_line(
+                timestamp,
+                &[SquadEvent::MapLoading {
+                    timestamp,
+                    map: String::from(map),
+                }],
+            )
+            .unwrap();
+        let mut g = GameState::new();
+        g.events_len = events.commit().unwrap();
+        g.events_tail = tail_hash(&log, g.events_len).unwrap();
+        save_state(path, &g, DEFAULT_BACKUPS).unwrap();
+        g
+    }
+
+    #[test]
+    fn restores_a_backup_whose_events_are_still_in_the_log() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let old = save_with_line(&path, 0, "Narva");
+        save_with_line(&path, old.events_len, "Gorodok");
+
+        assert!(restore_backup(&path, 1, DEFAULT_BACKUPS).unwrap());
+        let g = load_state(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(g.events_len, old.events_len);
+        assert!(g.events_complete);
+    }
+
+    #[test]
+    fn wont_point_a_backup_at_a_rewritten_event_log() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        let old = save_with_line(&path, 0, "Narva");
+        // At least as long as before, so its length alone can't tell.
+        let new = save_with_line(&path, 0, "Yehorivka/Gameplay_Layers/Yehorivka_RAAS_v1");
+        assert!(new.events_len >= old.events_len);
+        assert_ne!(new.events_tail, old.events_tail);
+
+        assert!(!restore_backup(&path, 1, DEFAULT_BACKUPS).unwrap());
+        let g = load_state(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(g.events_len, new.events_len);
+        assert_eq!(g.events_tail, new.events_tail);
+        assert!(!g.events_complete);
+    }
+}
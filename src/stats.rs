@@ -0,0 +1,458 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use identity::{merge_aliases, Alias};
+use state::{effective_identity, weapons_by_name, Game, GameState, Player, WeaponStats};
+use timezones::in_zone;
+use weapons::{WeaponCatalog, WeaponCategory};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PlayerOutput {
+    pub name: String,
+    // The identity these stats belong to, if we have online IDs for them.
+    pub identity: Option<String>,
+    // Every name the identity has played under, oldest first.
+    pub aliases: Vec<Alias>,
+    pub count_kills: u32,
+    pub count_killed: u32,
+    pub count_revives: u32,
+    pub count_revived: u32,
+    pub kills: HashMap<String, u32>,
+    pub killed_by: HashMap<String, u32>,
+    pub revives: HashMap<String, u32>,
+    pub revived_by: HashMap<String, u32>,
+    pub classes: HashSet<String>,
+    pub count_downs: u32,
+    // Median seconds this player took to pick up a downed teammate.
+    pub median_time_to_revive: Option<f64>,
+    // How many of this player's downs ended in a revive.
+    pub revive_rate_per_down: Option<f64>,
+    // Most lethal first.
+    pub weapons: Vec<WeaponOutput>,
+    pub categories: Vec<CategoryOutput>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WeaponOutput {
+    // The blueprint class, with the instance number stripped.
+    pub weapon: String,
+    pub name: String,
+    pub category: WeaponCategory,
+    pub damage_dealt: f32,
+    pub downs_inflicted: u32,
+    pub downs_suffered: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CategoryOutput {
+    pub category: WeaponCategory,
+    pub damage_dealt: f32,
+    pub downs_inflicted: u32,
+    pub downs_suffered: u32,
+}
+
+fn sum_map(m: &HashMap<String, u32>) -> u32 {
+    let mut res: u32 = 0;
+
+    for c in m.values() {
+        res += c;
+    }
+
+    res
+}
+
+fn median(v: &mut [f64]) -> Option<f64> {
+    if v.is_empty() {
+        return None;
+    }
+
+    v.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mid = v.len() / 2;
+    if v.len().is_multiple_of(2) {
+        Some((v[mid - 1] + v[mid]) / 2.0)
+    } else {
+        Some(v[mid])
+    }
+}
+
+// Sum raw weapon counters by their normalized class name.
+fn normalize_weapons(
+    weapons: &HashMap<String, WeaponStats>,
+    totals: &mut HashMap<String, WeaponStats>,
+) {
+    for (weapon, w) in weapons_by_name(weapons) {
+        totals
+            .entry(WeaponCatalog::normalize(weapon))
+            .or_default()
+            .add(w);
+    }
+}
+
+fn more_lethal(a: &WeaponStats, b: &WeaponStats) -> ::std::cmp::Ordering {
+    b.downs_inflicted
+        .cmp(&a.downs_inflicted)
+        .then(b.damage_dealt.partial_cmp(&a.damage_dealt).unwrap())
+}
+
+// Order weapons by downs inflicted, then by damage dealt, then by name so the
+// output is stable.
+fn rank_weapons(
+    weapons: &HashMap<String, WeaponStats>,
+    catalog: &WeaponCatalog,
+) -> Vec<WeaponOutput> {
+    let mut ranked: Vec<(&String, &WeaponStats)> = weapons.iter().collect();
+    ranked.sort_by(|(an, a), (bn, b)| more_lethal(a, b).then(an.cmp(bn)));
+    ranked
+        .into_iter()
+        .map(|(weapon, w)| {
+            let info = catalog.lookup(weapon);
+            WeaponOutput {
+                weapon: weapon.clone(),
+                name: info.name,
+                category: info.category,
+                damage_dealt: w.damage_dealt,
+                downs_inflicted: w.downs_inflicted,
+                downs_suffered: w.downs_suffered,
+            }
+        })
+        .collect()
+}
+
+fn rank_categories(
+    weapons: &HashMap<String, WeaponStats>,
+    catalog: &WeaponCatalog,
+) -> Vec<CategoryOutput> {
+    let mut totals: HashMap<WeaponCategory, WeaponStats> = HashMap::new();
+    for (weapon, w) in weapons_by_name(weapons) {
+        totals
+            .entry(catalog.lookup(weapon).category)
+            .or_default()
+            .add(w);
+    }
+
+    let mut ranked: Vec<(WeaponCategory, WeaponStats)> = totals.into_iter().collect();
+    ranked.sort_by(|(ac, a), (bc, b)| more_lethal(a, b).then(ac.cmp(bc)));
+    ranked
+        .into_iter()
+        .map(|(category, w)| CategoryOutput {
+            category,
+            damage_dealt: w.damage_dealt,
+            downs_inflicted: w.downs_inflicted,
+            downs_suffered: w.downs_suffered,
+        })
+        .collect()
+}
+
+fn all_weapons(g: &GameState) -> HashMap<String, WeaponStats> {
+    let mut totals: HashMap<String, WeaponStats> = HashMap::new();
+
+    for game in &g.games {
+        for player_state in game.players.values() {
+            normalize_weapons(&player_state.weapons, &mut totals);
+        }
+    }
+
+    totals
+}
+
+// Every weapon used in any game, most lethal first.
+pub fn weapon_leaderboard(g: &GameState, catalog: &WeaponCatalog) -> Vec<WeaponOutput> {
+    rank_weapons(&all_weapons(g), catalog)
+}
+
+// The same as weapon_leaderboard, but summed by weapon category.
+pub fn category_leaderboard(g: &GameState, catalog: &WeaponCatalog) -> Vec<CategoryOutput> {
+    rank_categories(&all_weapons(g), catalog)
+}
+
+// Players we have online IDs for, or that the admin has told us about, are
+// counted by identity, so they keep their stats across renames. Anyone else is
+// counted by name.
+pub fn player_key(g: &GameState, game: &Game, p: &Player) -> String {
+    match effective_identity(g, game, p) {
+        Some(identity) => identity,
+        None => p.name.clone(),
+    }
+}
+
+// Re-key a map of per-opponent counters from a game's player names to player
+// keys.
+fn rekey(m: &HashMap<String, u32>, g: &GameState, game: &Game) -> HashMap<String, u32> {
+    let mut res = HashMap::new();
+    for (n, c) in m {
+        let key = match game.players.get(n) {
+            Some(p) => player_key(g, game, p),
+            None => n.clone(),
+        };
+        *res.entry(key).or_insert(0) += c;
+    }
+    res
+}
+
+fn relabel(m: &HashMap<String, u32>, labels: &HashMap<String, String>) -> HashMap<String, u32> {
+    let mut res = HashMap::new();
+    for (key, c) in m {
+        let label = labels.get(key).unwrap_or(key);
+        *res.entry(label.clone()).or_insert(0) += c;
+    }
+    res
+}
+
+// The name each player's stats are listed under, by player key: the name their
+// identity was last seen under, or failing that the last name they played a
+// game under. Should two players end up with the same label, the later ones
+// get their identity added to tell them apart.
+pub fn player_labels(g: &GameState) -> HashMap<String, String> {
+    let mut last_names: HashMap<String, String> = HashMap::new();
+    for game in &g.games {
+        for (player_name, player_state) in &game.players {
+            last_names.insert(player_key(g, game, player_state), player_name.clone());
+        }
+    }
+
+    let mut keys: Vec<&String> = last_names.keys().collect();
+    keys.sort();
+    let mut labels: HashMap<String, String> = HashMap::new();
+    let mut used: HashSet<String> = HashSet::new();
+    for key in keys {
+        let name = match g.identities.name_of(key) {
+            Some(n) => n.clone(),
+            None => last_names[key].clone(),
+        };
+        let label = if used.contains(&name) {
+            format!("{} [{}]", name, key)
+        } else {
+            name
+        };
+        used.insert(label.clone());
+        labels.insert(key.clone(), label);
+    }
+    labels
+}
+
+// Fold every game into one set of per-player totals, keyed by the name each
+// player was last seen under.
+pub fn lifetime_stats(g: &GameState, catalog: &WeaponCatalog) -> HashMap<String, PlayerOutput> {
+    let mut lifetime_players: HashMap<String, PlayerOutput> = HashMap::new();
+    let mut revive_times: HashMap<String, Vec<f64>> = HashMap::new();
+    let mut weapon_totals: HashMap<String, HashMap<String, WeaponStats>> = HashMap::new();
+    let mut aliases: HashMap<String, Vec<Alias>> = HashMap::new();
+
+    for game in &g.games {
+        for (player_name, player_state) in &game.players {
+            let key = player_key(g, game, player_state);
+            if let Some(identity) = &player_state.identity {
+                merge_aliases(
+                    aliases.entry(key.clone()).or_default(),
+                    g.identities.aliases_of(identity),
+                );
+            }
+            revive_times
+                .entry(key.clone())
+                .or_default()
+                .extend(player_state.revives.iter().filter_map(|r| r.time_down));
+            normalize_weapons(
+                &player_state.weapons,
+                weapon_totals.entry(key.clone()).or_default(),
+            );
+
+            let players_killed = rekey(&player_state.players_killed, g, game);
+            let players_killed_by = rekey(&player_state.players_killed_by, g, game);
+            let players_revived = rekey(&player_state.players_revived, g, game);
+            let players_revived_by = rekey(&player_state.players_revived_by, g, game);
+
+            let updt = match lifetime_players.get(&key) {
+                Some(p) => {
+                    // Merge everything.
+                    let mut new_kills = p.kills.clone();
+                    for (n, c) in &players_killed {
+                        *new_kills.entry(n.clone()).or_insert(0) += c;
+                    }
+                    let mut new_kills_by = p.killed_by.clone();
+                    for (n, c) in &players_killed_by {
+                        *new_kills_by.entry(n.clone()).or_insert(0) += c;
+                    }
+                    let mut new_revives = p.revives.clone();
+                    for (n, c) in &players_revived {
+                        *new_revives.entry(n.clone()).or_insert(0) += c;
+                    }
+                    let mut new_revived_by = p.revived_by.clone();
+                    for (n, c) in &players_revived_by {
+                        *new_revived_by.entry(n.clone()).or_insert(0) += c;
+                    }
+
+                    PlayerOutput {
+                        count_kills: p.count_kills + sum_map(&players_killed),
+                        count_killed: p.count_killed + sum_map(&players_killed_by),
+                        count_revived: p.count_revived + sum_map(&players_revived_by),
+                        count_revives: p.count_revives + sum_map(&players_revived),
+                        kills: new_kills,
+                        killed_by: new_kills_by,
+                        revives: new_revives,
+                        revived_by: new_revived_by,
+                        classes: p
+                            .classes
+                            .union(&player_state.classes_played)
+                            .cloned()
+                            .collect(),
+                        count_downs: p.count_downs + player_state.downs,
+                        ..p.clone()
+                    }
+                }
+                None => PlayerOutput {
+                    name: player_name.clone(),
+                    identity: effective_identity(g, game, player_state),
+                    aliases: Vec::new(),
+                    count_kills: sum_map(&players_killed),
+                    count_killed: sum_map(&players_killed_by),
+                    count_revived: sum_map(&players_revived_by),
+                    count_revives: sum_map(&players_revived),
+                    kills: players_killed,
+                    killed_by: players_killed_by,
+                    revives: players_revived,
+                    revived_by: players_revived_by,
+                    classes: player_state.classes_played.clone(),
+                    count_downs: player_state.downs,
+                    median_time_to_revive: None,
+                    revive_rate_per_down: None,
+                    weapons: Vec::new(),
+                    categories: Vec::new(),
+                },
+            };
+            lifetime_players.insert(key, updt);
+        }
+    }
+
+    let labels = player_labels(g);
+
+    let mut labelled_players = HashMap::new();
+    for (key, mut p) in lifetime_players {
+        if let Some(times) = revive_times.get_mut(&key) {
+            p.median_time_to_revive = median(times);
+        }
+        if let Some(weapons) = weapon_totals.get(&key) {
+            p.weapons = rank_weapons(weapons, catalog);
+            p.categories = rank_categories(weapons, catalog);
+        }
+        if p.count_downs > 0 {
+            p.revive_rate_per_down = Some(f64::from(p.count_revived) / f64::from(p.count_downs));
+        }
+        p.kills = relabel(&p.kills, &labels);
+        p.killed_by = relabel(&p.killed_by, &labels);
+        p.revives = relabel(&p.revives, &labels);
+        p.revived_by = relabel(&p.revived_by, &labels);
+        if let Some(a) = aliases.remove(&key) {
+            let zone = g.timezones.display_zone();
+            p.aliases = a
+                .into_iter()
+                .map(|a| Alias {
+                    first_seen: in_zone(&a.first_seen, zone),
+                    last_seen: in_zone(&a.last_seen, zone),
+                    ..a
+                })
+                .collect();
+            p.aliases.sort_by_key(|a| a.first_seen);
+        }
+        p.name = labels[&key].clone();
+        labelled_players.insert(p.name.clone(), p);
+    }
+
+    labelled_players
+}
+
+// Everything the report command prints.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct LifetimeOutput {
+    players: HashMap<String, PlayerOutput>,
+    weapon_leaderboard: Vec<WeaponOutput>,
+    category_leaderboard: Vec<CategoryOutput>,
+}
+
+// Prints the per-player totals and the overall weapon and weapon category
+// leaderboards as one line of JSON.
+pub fn print_lifetime_stats(g: &GameState, catalog: &WeaponCatalog) {
+    let output = LifetimeOutput {
+        players: lifetime_stats(g, catalog),
+        weapon_leaderboard: weapon_leaderboard(g, catalog),
+        category_leaderboard: category_leaderboard(g, catalog),
+    };
+    println!(
+        "{}",
+        serde_json::to_string(&output).expect("serialization error")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use event::SquadEvent;
+    use parse::get_dt;
+    use state::apply_event;
+
+    fn wound(t: &str, victim: &str) -> SquadEvent {
+        SquadEvent::Wounded {
+            timestamp: get_dt(t).unwrap(),
+            victim: String::from(victim),
+            damage: 100.0,
+            attacker: String::from("Alice"),
+            weapon: String::from("BP_M4_C_1"),
+        }
+    }
+
+    fn revive(t: &str, revivee: &str) -> SquadEvent {
+        SquadEvent::Revived {
+            timestamp: get_dt(t).unwrap(),
+            reviver: String::from("Carol"),
+            revivee: String::from(revivee),
+        }
+    }
+
+    #[test]
+    fn medians_revive_times_and_counts_revives_per_down() {
+        let t = get_dt("2026.10.01-10.00.00:000").unwrap();
+        let mut events = vec![SquadEvent::MapLoading {
+            timestamp: t,
+            map: String::from("Narva"),
+        }];
+        for name in &["Alice", "Bob", "Carol", "Dave"] {
+            events.push(SquadEvent::ControllerStateChanged {
+                timestamp: t,
+                controller: String::from(*name),
+                old_state: String::from("Inactive"),
+                new_state: String::from("Playing"),
+            });
+            events.push(SquadEvent::RoleChanged {
+                timestamp: t,
+                player: String::from(*name),
+                role: String::from("USA_Rifleman_01"),
+            });
+        }
+        // Carol picks Bob up after 10s and 30s, and Dave after 5s, but
+        // leaves him down the third time.
+        events.extend(vec![
+            wound("2026.10.01-10.01.00:000", "Bob"),
+            revive("2026.10.01-10.01.10:000", "Bob"),
+            wound("2026.10.01-10.02.00:000", "Bob"),
+            revive("2026.10.01-10.02.30:000", "Bob"),
+            wound("2026.10.01-10.03.00:000", "Dave"),
+            revive("2026.10.01-10.03.05:000", "Dave"),
+            wound("2026.10.01-10.04.00:000", "Dave"),
+        ]);
+        let mut g = GameState::new();
+        for event in &events {
+            apply_event(event, &mut g).unwrap();
+        }
+
+        let stats = lifetime_stats(&g, &WeaponCatalog::builtin());
+        let carol = &stats["Carol"];
+        assert_eq!(carol.count_revives, 3);
+        assert_eq!(carol.median_time_to_revive, Some(10.0));
+        assert_eq!(stats["Bob"].count_downs, 2);
+        assert_eq!(stats["Bob"].revive_rate_per_down, Some(1.0));
+        assert_eq!(stats["Dave"].revive_rate_per_down, Some(0.5));
+        // Nobody went down, so there's nothing to divide by.
+        assert_eq!(carol.revive_rate_per_down, None);
+        assert_eq!(stats["Alice"].median_time_to_revive, None);
+    }
+}
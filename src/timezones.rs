@@ -0,0 +1,71 @@
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Which timezone each server logs in, and which one reports show times in,
+// kept in a TOML file like:
+//
+//   display = "America/New_York"
+//   source = "UTC"
+//
+//   [[file]]
+//   path = "logs/eu-*/*.log"
+//   zone = "Europe/Berlin"
+//
+// Zones are IANA names. Log paths are matched, as given on the command line,
+// against each file glob in turn; logs that match none are in source.
+// Anything left out is UTC.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Timezones {
+    #[serde(default)]
+    pub display: Option<Tz>,
+    #[serde(default)]
+    pub source: Option<Tz>,
+    #[serde(default)]
+    pub file: Vec<FileZone>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FileZone {
+    pub path: String,
+    pub zone: Tz,
+}
+
+impl Timezones {
+    pub fn from_file(path: &Path) -> io::Result<Timezones> {
+        let contents = fs::read_to_string(path)?;
+        let zones: Timezones =
+            toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        for f in &zones.file {
+            Pattern::new(&f.path).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", f.path, e))
+            })?;
+        }
+        Ok(zones)
+    }
+
+    // The zone the log at path was written in.
+    pub fn source_zone(&self, path: &Path) -> Tz {
+        for f in &self.file {
+            if let Ok(pattern) = Pattern::new(&f.path) {
+                if pattern.matches_path(path) {
+                    return f.zone;
+                }
+            }
+        }
+        self.source.unwrap_or(Tz::UTC)
+    }
+
+    pub fn display_zone(&self) -> Tz {
+        self.display.unwrap_or(Tz::UTC)
+    }
+}
+
+// The same instant as t, with the offset it had in zone.
+pub fn in_zone(t: &DateTime<FixedOffset>, zone: Tz) -> DateTime<FixedOffset> {
+    t.with_timezone(&zone).fixed_offset()
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// The catalog that ships with squadlog. Admins can extend or override entries
+// with their own file in the same format.
+const BUILTIN_CATALOG: &str = include_str!("weapons.json");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WeaponCategory {
+    Rifle,
+    Lmg,
+    Sniper,
+    Explosive,
+    VehicleWeapon,
+    Melee,
+    Deployable,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WeaponInfo {
+    pub name: String,
+    pub category: WeaponCategory,
+}
+
+// Maps blueprint class names, with their instance numbers stripped, to a
+// display name and a category.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct WeaponCatalog {
+    pub weapons: HashMap<String, WeaponInfo>,
+}
+
+fn invalid_data(e: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+impl WeaponCatalog {
+    pub fn builtin() -> WeaponCatalog {
+        serde_json::from_str(BUILTIN_CATALOG).expect("built-in weapon catalog is invalid")
+    }
+
+    // Add the entries from a catalog file on top of this one. Entries in the
+    // file win over ones we already have.
+    pub fn extend_from_file(&mut self, path: &Path) -> io::Result<()> {
+        let contents = fs::read_to_string(path)?;
+        let extra: WeaponCatalog = serde_json::from_str(&contents).map_err(invalid_data)?;
+        self.weapons.extend(extra.weapons);
+        Ok(())
+    }
+
+    // Strip the instance number the engine appends to a blueprint class, so
+    // BP_M4A1_M68_C_2147481234 becomes BP_M4A1_M68_C.
+    pub fn normalize(raw: &str) -> String {
+        if let Some(idx) = raw.rfind('_') {
+            let (class, suffix) = (&raw[..idx], &raw[idx + 1..]);
+            if class.ends_with("_C")
+                && !suffix.is_empty()
+                && suffix.bytes().all(|b| b.is_ascii_digit())
+            {
+                return String::from(class);
+            }
+        }
+        String::from(raw)
+    }
+
+    // Look up a raw weapon name from the log. Weapons we don't know get a name
+    // made up from their class name and the Unknown category.
+    pub fn lookup(&self, raw: &str) -> WeaponInfo {
+        let class = WeaponCatalog::normalize(raw);
+        match self.weapons.get(&class) {
+            Some(info) => info.clone(),
+            None => {
+                let trimmed = class.trim_start_matches("BP_").trim_end_matches("_C");
+                WeaponInfo {
+                    name: trimmed.replace('_', " "),
+                    category: WeaponCategory::Unknown,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_instance_numbers() {
+        assert_eq!(
+            WeaponCatalog::normalize("BP_M4A1_M68_C_2147481234"),
+            "BP_M4A1_M68_C"
+        );
+        assert_eq!(WeaponCatalog::normalize("BP_M4A1_M68_C"), "BP_M4A1_M68_C");
+        // Only a number after _C is an instance number.
+        assert_eq!(WeaponCatalog::normalize("BP_Mk19_2"), "BP_Mk19_2");
+        assert_eq!(WeaponCatalog::normalize("BP_G3_C_"), "BP_G3_C_");
+        assert_eq!(WeaponCatalog::normalize("nullptr"), "nullptr");
+    }
+
+    #[test]
+    fn looks_weapons_up_by_class() {
+        let catalog = WeaponCatalog::builtin();
+        let m249 = catalog.lookup("BP_M249_C_2147480001");
+        assert_eq!(m249.name, "M249");
+        assert_eq!(m249.category, WeaponCategory::Lmg);
+
+        let unknown = catalog.lookup("BP_Made_Up_Gun_C_12");
+        assert_eq!(unknown.name, "Made Up Gun");
+        assert_eq!(unknown.category, WeaponCategory::Unknown);
+    }
+}